@@ -14794,6 +14794,8 @@ pub type zconfig_fct =
                          arg: *mut ::std::os::raw::c_void,
                          level: ::std::os::raw::c_int)
         -> ::std::os::raw::c_int;
+pub type zframe_destructor_fn =
+    unsafe extern "C" fn(hint: *mut *mut ::std::os::raw::c_void);
 pub type zhash_free_fn =
     unsafe extern "C" fn(data: *mut ::std::os::raw::c_void);
 pub type zhash_foreach_fn =
@@ -18752,6 +18754,9 @@ extern "C" {
     pub fn zframe_new_empty() -> *mut zframe_t;
     pub fn zframe_from(string: *const ::std::os::raw::c_char)
      -> *mut zframe_t;
+    pub fn zframe_frommem(data: *mut ::std::os::raw::c_void, size: size_t,
+                          destructor: zframe_destructor_fn,
+                          hint: *mut ::std::os::raw::c_void) -> *mut zframe_t;
     pub fn zframe_recv(source: *mut ::std::os::raw::c_void) -> *mut zframe_t;
     pub fn zframe_destroy(self_p: *mut *mut zframe_t);
     pub fn zframe_send(self_p: *mut *mut zframe_t,
@@ -19,6 +19,11 @@ pub use ffi::{
     //
     zauth,
 
+    //
+    // ZBeacon
+    //
+    zbeacon,
+
     //
     // ZCert
     //
@@ -66,6 +71,8 @@ pub use ffi::{
     zframe_new,
     zframe_new_empty,
     zframe_from,
+    zframe_frommem,
+    zframe_destructor_fn,
     zframe_recv,
     zframe_destroy,
     zframe_send,
@@ -81,6 +88,32 @@ pub use ffi::{
     zframe_eq,
     zframe_reset,
     zframe_print,
+    zframe_routing_id,
+    zframe_set_routing_id,
+    zframe_group,
+    zframe_set_group,
+
+    //
+    // ZGossip
+    //
+    zgossip,
+    zgossip_msg_t,
+    zgossip_msg_new,
+    zgossip_msg_destroy,
+    zgossip_msg_recv,
+    zgossip_msg_send,
+    zgossip_msg_print,
+    zgossip_msg_routing_id,
+    zgossip_msg_set_routing_id,
+    zgossip_msg_id,
+    zgossip_msg_set_id,
+    zgossip_msg_command,
+    zgossip_msg_key,
+    zgossip_msg_set_key,
+    zgossip_msg_value,
+    zgossip_msg_set_value,
+    zgossip_msg_ttl,
+    zgossip_msg_set_ttl,
 
     //
     // ZHashX
@@ -127,6 +160,28 @@ pub use ffi::{
     zlist_next,
     zlist_size,
 
+    //
+    // ZLoop
+    //
+    zloop_t,
+    zloop_reader_fn,
+    zloop_timer_fn,
+    zloop_new,
+    zloop_destroy,
+    zloop_reader,
+    zloop_reader_end,
+    zloop_reader_set_tolerant,
+    zloop_timer,
+    zloop_timer_end,
+    zloop_ticket,
+    zloop_ticket_reset,
+    zloop_ticket_delete,
+    zloop_set_ticket_delay,
+    zloop_set_max_timers,
+    zloop_set_verbose,
+    zloop_start,
+    zloop_set_nonstop,
+
     //
     // ZMonitor
     //
@@ -176,6 +231,8 @@ pub use ffi::{
     zmsg_push,
     zmsg_add,
     zmsg_fprint,
+    zmsg_routing_id,
+    zmsg_set_routing_id,
 
     //
     // ZPoller
@@ -190,6 +247,11 @@ pub use ffi::{
     zpoller_terminated,
     zpoller_set_nonstop,
 
+    //
+    // ZProxy
+    //
+    zproxy,
+
     //
     // ZSock
     //
@@ -207,6 +269,16 @@ pub use ffi::{
     zsock_new_xsub,
     zsock_new_pair,
     zsock_new_stream,
+    zsock_new_server,
+    zsock_new_client,
+    zsock_new_radio,
+    zsock_new_dish,
+    zsock_new_gather,
+    zsock_new_scatter,
+    zsock_routing_id,
+    zsock_set_routing_id,
+    zsock_join,
+    zsock_leave,
     zsock_destroy,
     zsock_bind,
     zsock_endpoint,
@@ -340,6 +412,20 @@ pub use ffi::{
     zsys_init,
     zsys_create_pipe,
     zsys_interrupted,
+    zsys_set_interface,
+    zsys_interface,
+
+    //
+    // ZMQ
+    //
+    zmq_errno,
+    zmq_poll,
+    zmq_pollitem_t,
+
+    //
+    // C types
+    //
+    size_t,
 };
 
 #[allow(dead_code, non_camel_case_types, non_snake_case)]
@@ -0,0 +1,541 @@
+//! Module: czmq-async
+//!
+//! Non-blocking ZSock I/O for async runtimes. Sockets are driven by
+//! their ZMQ_FD, which a single background thread polls on behalf of
+//! every pending future.
+
+use {Result, ZMsg, ZSock, ZSockEvents, ZSOCK_POLLIN, ZSOCK_POLLOUT};
+#[cfg(feature = "stream")]
+use {Error, ErrorKind};
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+#[cfg(feature = "stream")]
+use futures_sink::Sink;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+#[cfg(feature = "stream")]
+use std::{error, fmt};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use zmq;
+
+/// Wraps a ZSock so that sends and receives return futures instead of
+/// blocking the calling thread.
+///
+/// With the `stream` feature, it is also a `Stream` of received
+/// messages and a `Sink` for outgoing ones. The sink holds at most one
+/// message that the socket couldn't yet accept, so senders are held
+/// back whenever the socket is at its send HWM; `start_send()` fails
+/// with `ErrorKind::InvalidArg` if called again before `poll_ready()`
+/// has made room. The stream yields the first receive error it hits
+/// and then ends, as that means the socket or its context has gone.
+///
+/// ```no_run
+/// # use czmq::{AsyncZSock, ZMsg, ZSock};
+/// # use std::future::Future;
+/// fn next_msg<'a>(pull: &'a mut AsyncZSock) -> impl Future<Output = czmq::Result<ZMsg>> + 'a {
+///     pull.recv()
+/// }
+///
+/// let mut pull = AsyncZSock::new(ZSock::new_pull("@tcp://127.0.0.1:5555").unwrap());
+/// // Await this, or hand it to your runtime's block_on()
+/// let msg = next_msg(&mut pull);
+/// ```
+pub struct AsyncZSock {
+    zsock: ZSock,
+    #[cfg(feature = "stream")]
+    pending: Option<ZMsg>,
+    #[cfg(feature = "stream")]
+    failed: bool,
+}
+
+impl AsyncZSock {
+    pub fn new(zsock: ZSock) -> AsyncZSock {
+        AsyncZSock {
+            zsock: zsock,
+            #[cfg(feature = "stream")]
+            pending: None,
+            #[cfg(feature = "stream")]
+            failed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &ZSock {
+        &self.zsock
+    }
+
+    pub fn get_mut(&mut self) -> &mut ZSock {
+        &mut self.zsock
+    }
+
+    /// Any message still buffered by the `Sink` impl is dropped.
+    pub fn into_inner(self) -> ZSock {
+        self.zsock
+    }
+
+    /// Returns a future that resolves once `msg` has been queued on
+    /// the socket.
+    pub fn send<'a>(&'a mut self, msg: ZMsg) -> ZSockSend<'a> {
+        ZSockSend {
+            sock: self,
+            msg: Some(msg),
+        }
+    }
+
+    /// Returns a future that resolves to the next message.
+    pub fn recv<'a>(&'a mut self) -> ZSockRecv<'a> {
+        ZSockRecv {
+            sock: self,
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Result<ZMsg>> {
+        match self.poll_events(cx, ZSOCK_POLLIN) {
+            Poll::Ready(Ok(())) => Poll::Ready(ZMsg::recv(&mut self.zsock)),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Resolves once the socket can accept a message without blocking.
+    /// Once the first frame of a multipart message is accepted, ZeroMQ
+    /// guarantees the rest will be too.
+    pub fn poll_send_ready(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        self.poll_events(cx, ZSOCK_POLLOUT)
+    }
+
+    // ZMQ_FD is edge-triggered: it only becomes readable when the
+    // socket's state changes, and reading ZMQ_EVENTS resets it. So we
+    // must always check ZMQ_EVENTS before waiting on the fd, and since
+    // that check may swallow an edge that other waiters on the same
+    // socket were relying on, pass on anything we saw to them.
+    fn poll_events(&mut self, cx: &mut Context, wanted: ZSockEvents) -> Poll<Result<()>> {
+        let events = match self.zsock.events() {
+            Ok(events) => events,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+
+        let fd = self.zsock.fd();
+        reactor().wake(fd, events);
+
+        if events.contains(wanted) {
+            Poll::Ready(Ok(()))
+        } else {
+            reactor().register(fd, wanted, cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+// Sockets have no end of stream, so the stream only ends after an
+// error.
+#[cfg(feature = "stream")]
+impl Stream for AsyncZSock {
+    type Item = Result<ZMsg>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Result<ZMsg>>> {
+        let this = self.get_mut();
+
+        if this.failed {
+            return Poll::Ready(None);
+        }
+
+        match this.poll_recv(cx) {
+            Poll::Ready(Ok(msg)) => Poll::Ready(Some(Ok(msg))),
+            Poll::Ready(Err(e)) => {
+                this.failed = true;
+                Poll::Ready(Some(Err(e)))
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl Sink<ZMsg> for AsyncZSock {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: ZMsg) -> Result<()> {
+        let this = self.get_mut();
+
+        // Replacing the held message would drop it without a trace
+        if this.pending.is_some() {
+            return Err(Error::new(ErrorKind::InvalidArg, AsyncZSockError::SinkFull));
+        }
+
+        this.pending = Some(msg);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        if this.pending.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+
+        match this.poll_send_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                let msg = this.pending.take().unwrap();
+                Poll::Ready(msg.send(&mut this.zsock))
+            },
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(feature = "stream")]
+#[derive(Debug)]
+pub enum AsyncZSockError {
+    SinkFull,
+}
+
+#[cfg(feature = "stream")]
+impl fmt::Display for AsyncZSockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsyncZSockError::SinkFull => write!(f, "Sink is still holding a message; wait for poll_ready() before start_send()"),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl error::Error for AsyncZSockError {
+    fn description(&self) -> &str {
+        match *self {
+            AsyncZSockError::SinkFull => "Sink is still holding a message",
+        }
+    }
+}
+
+pub struct ZSockSend<'a> {
+    sock: &'a mut AsyncZSock,
+    msg: Option<ZMsg>,
+}
+
+impl<'a> Future for ZSockSend<'a> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+
+        match this.sock.poll_send_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                let msg = this.msg.take().expect("ZSockSend polled after completion");
+                Poll::Ready(msg.send(&mut this.sock.zsock))
+            },
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct ZSockRecv<'a> {
+    sock: &'a mut AsyncZSock,
+}
+
+impl<'a> Future for ZSockRecv<'a> {
+    type Output = Result<ZMsg>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<ZMsg>> {
+        self.get_mut().sock.poll_recv(cx)
+    }
+}
+
+struct Waiter {
+    fd: RawFd,
+    wanted: ZSockEvents,
+    waker: Waker,
+}
+
+struct Reactor {
+    waiters: Arc<Mutex<Vec<Waiter>>>,
+    notify: UnixStream,
+}
+
+impl Reactor {
+    fn register(&self, fd: RawFd, wanted: ZSockEvents, waker: &Waker) {
+        {
+            let mut waiters = self.waiters.lock().unwrap();
+
+            if waiters.iter().any(|w| w.fd == fd && w.wanted == wanted && w.waker.will_wake(waker)) {
+                return;
+            }
+
+            waiters.push(Waiter {
+                fd: fd,
+                wanted: wanted,
+                waker: waker.clone(),
+            });
+        }
+
+        // Interrupt the poll so that it picks up the new fd. If the
+        // buffer is full, the reactor is already due to wake up.
+        let _ = (&self.notify).write(&[1]);
+    }
+
+    // Wake anyone on `fd` whose events are now ready.
+    fn wake(&self, fd: RawFd, events: ZSockEvents) {
+        let mut waiters = self.waiters.lock().unwrap();
+        let mut i = 0;
+
+        while i < waiters.len() {
+            if waiters[i].fd == fd && waiters[i].wanted.intersects(events) {
+                waiters.swap_remove(i).waker.wake();
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+
+    REACTOR.get_or_init(|| {
+        let (notify, wakeup) = UnixStream::pair().expect("Could not create reactor pipe");
+        notify.set_nonblocking(true).expect("Could not create reactor pipe");
+        wakeup.set_nonblocking(true).expect("Could not create reactor pipe");
+
+        let waiters = Arc::new(Mutex::new(Vec::new()));
+        let thread_waiters = waiters.clone();
+
+        thread::Builder::new()
+            .name("czmq-async".into())
+            .spawn(move || run_reactor(wakeup, thread_waiters))
+            .expect("Could not spawn reactor thread");
+
+        Reactor {
+            waiters: waiters,
+            notify: notify,
+        }
+    })
+}
+
+fn run_reactor(mut wakeup: UnixStream, waiters: Arc<Mutex<Vec<Waiter>>>) {
+    loop {
+        let mut fds = vec![wakeup.as_raw_fd()];
+        for w in waiters.lock().unwrap().iter() {
+            if !fds.contains(&w.fd) {
+                fds.push(w.fd);
+            }
+        }
+
+        let mut items: Vec<_> = fds.iter().map(|&fd| {
+            let mut item = zmq::PollItem::from_fd(fd);
+            item.set_events(zmq::POLLIN);
+            item
+        }).collect();
+
+        // Interrupted system calls are harmless; just poll again.
+        if zmq::poll(&mut items, -1).is_err() {
+            continue;
+        }
+
+        if items[0].get_revents() != 0 {
+            let mut buf = [0; 64];
+            while let Ok(n) = wakeup.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+
+        // We can't tell which events the fd is signalling, so wake
+        // everyone on it and let them check ZMQ_EVENTS. This also
+        // catches closed fds, which poll reports as errors.
+        let ready: Vec<RawFd> = fds.iter()
+            .zip(items.iter())
+            .skip(1)
+            .filter(|&(_, item)| item.get_revents() != 0)
+            .map(|(&fd, _)| fd)
+            .collect();
+
+        if !ready.is_empty() {
+            let mut waiters = waiters.lock().unwrap();
+            let mut i = 0;
+
+            while i < waiters.len() {
+                if ready.contains(&waiters[i].fd) {
+                    waiters.swap_remove(i).waker.wake();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, sleep, Thread};
+    use std::time::Duration;
+    use super::*;
+    use {ZMsg, ZSock, ZSys};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(mut future: F) -> F::Output {
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = unsafe { Pin::new_unchecked(&mut future) };
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn msg(s: &str) -> ZMsg {
+        let msg = ZMsg::new();
+        msg.addstr(s).unwrap();
+        msg
+    }
+
+    #[test]
+    fn test_inproc() {
+        ZSys::init();
+
+        let mut pull = AsyncZSock::new(ZSock::new_pull("@inproc://async_zsock_test_inproc").unwrap());
+        let mut push = AsyncZSock::new(ZSock::new_push(">inproc://async_zsock_test_inproc").unwrap());
+
+        block_on(push.send(msg("moo"))).unwrap();
+        let received = block_on(pull.recv()).unwrap();
+        assert_eq!(received.popstr().unwrap().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_recv_wakes() {
+        ZSys::init();
+
+        let mut pull = AsyncZSock::new(ZSock::new_pull("@inproc://async_zsock_test_recv_wakes").unwrap());
+
+        let handle = thread::spawn(|| {
+            sleep(Duration::from_millis(100));
+            let push = ZSock::new_push(">inproc://async_zsock_test_recv_wakes").unwrap();
+            push.send_str("moo").unwrap();
+            // Keep the socket alive until the message is delivered
+            sleep(Duration::from_millis(100));
+        });
+
+        let received = block_on(pull.recv()).unwrap();
+        assert_eq!(received.popstr().unwrap().unwrap(), "moo");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_send_wakes() {
+        ZSys::init();
+
+        // A PUSH socket with no peers can't send until one connects
+        let mut push = AsyncZSock::new(ZSock::new_push("@inproc://async_zsock_test_send_wakes").unwrap());
+
+        let handle = thread::spawn(|| {
+            sleep(Duration::from_millis(100));
+            let pull = ZSock::new_pull(">inproc://async_zsock_test_send_wakes").unwrap();
+            pull.set_rcvtimeo(Some(1000));
+            pull.recv_str().unwrap().unwrap()
+        });
+
+        block_on(push.send(msg("moo"))).unwrap();
+        assert_eq!(handle.join().unwrap(), "moo");
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_stream_sink() {
+        use futures_core::Stream;
+        use futures_sink::Sink;
+        use std::future::poll_fn;
+
+        ZSys::init();
+
+        let mut pull = AsyncZSock::new(ZSock::new_pull("@inproc://async_zsock_test_stream_sink").unwrap());
+        let mut push = AsyncZSock::new(ZSock::new_push(">inproc://async_zsock_test_stream_sink").unwrap());
+
+        for i in 0..10 {
+            block_on(poll_fn(|cx| Pin::new(&mut push).poll_ready(cx))).unwrap();
+            Pin::new(&mut push).start_send(msg(&i.to_string())).unwrap();
+        }
+        block_on(poll_fn(|cx| Pin::new(&mut push).poll_flush(cx))).unwrap();
+
+        for i in 0..10 {
+            let received = block_on(poll_fn(|cx| Pin::new(&mut pull).poll_next(cx))).unwrap().unwrap();
+            assert_eq!(received.popstr().unwrap().unwrap(), i.to_string());
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_sink_backpressure() {
+        use futures_sink::Sink;
+        use std::future::poll_fn;
+
+        ZSys::init();
+
+        // With no peer, the first message is held by the sink and the
+        // sink then refuses to accept another.
+        let mut push = AsyncZSock::new(ZSock::new_push("@inproc://async_zsock_test_sink_backpressure").unwrap());
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(Pin::new(&mut push).poll_ready(&mut cx).is_ready());
+        Pin::new(&mut push).start_send(msg("moo")).unwrap();
+        assert!(Pin::new(&mut push).poll_ready(&mut cx).is_pending());
+
+        // Sending anyway is refused rather than dropping "moo"
+        let err = Pin::new(&mut push).start_send(msg("cow")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArg);
+
+        let pull = ZSock::new_pull(">inproc://async_zsock_test_sink_backpressure").unwrap();
+        pull.set_rcvtimeo(Some(1000));
+        block_on(poll_fn(|cx| Pin::new(&mut push).poll_flush(cx))).unwrap();
+        assert_eq!(pull.recv_str().unwrap().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_tcp_queued() {
+        ZSys::init();
+
+        let pull = ZSock::new_pull("@tcp://127.0.0.1:*").unwrap();
+        let endpoint = pull.last_endpoint().unwrap();
+        let mut pull = AsyncZSock::new(pull);
+        let mut push = AsyncZSock::new(ZSock::new_push(&format!(">{}", endpoint)).unwrap());
+
+        // Queue up several messages before receiving any, so only the
+        // first is announced by the fd.
+        for i in 0..100 {
+            block_on(push.send(msg(&i.to_string()))).unwrap();
+        }
+
+        for i in 0..100 {
+            let received = block_on(pull.recv()).unwrap();
+            assert_eq!(received.popstr().unwrap().unwrap(), i.to_string());
+        }
+    }
+}
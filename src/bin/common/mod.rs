@@ -0,0 +1,67 @@
+//! Shared by czmq-capture and czmq-replay.
+//!
+//! A capture file is a series of messages in the `ZMsgFileWriter`
+//! format, each prefixed with an extra frame holding the time it was
+//! captured, as big-endian microseconds since the Unix epoch.
+
+// Each binary only uses some of these.
+#![allow(dead_code)]
+
+use czmq::{SocketType, ZFrame, ZMsg};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn parse_sock_type(name: &str) -> Option<SocketType> {
+    match &*name.to_uppercase() {
+        "PAIR" => Some(SocketType::PAIR),
+        "PUB" => Some(SocketType::PUB),
+        "SUB" => Some(SocketType::SUB),
+        "REQ" => Some(SocketType::REQ),
+        "REP" => Some(SocketType::REP),
+        "DEALER" => Some(SocketType::DEALER),
+        "ROUTER" => Some(SocketType::ROUTER),
+        "PULL" => Some(SocketType::PULL),
+        "PUSH" => Some(SocketType::PUSH),
+        "XPUB" => Some(SocketType::XPUB),
+        "XSUB" => Some(SocketType::XSUB),
+        "STREAM" => Some(SocketType::STREAM),
+        _ => None,
+    }
+}
+
+pub fn now_micros() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    now.as_secs() * 1_000_000 + now.subsec_micros() as u64
+}
+
+pub fn timestamp_frame(micros: u64) -> ZFrame {
+    ZFrame::new(&micros.to_be_bytes()).expect("Could not create timestamp frame")
+}
+
+/// Pop the timestamp frame off a captured message.
+pub fn pop_timestamp(msg: &ZMsg) -> Option<u64> {
+    let frame = match msg.pop() {
+        Some(f) => f,
+        None => return None,
+    };
+
+    if frame.size() != 8 {
+        return None;
+    }
+
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(frame.as_bytes());
+    Some(u64::from_be_bytes(bytes))
+}
+
+/// Print a message with one line per frame, each in `ZFrame::strhex()`
+/// form.
+pub fn dump(micros: u64, msg: &ZMsg) {
+    println!("[{}.{:06}] {} frame(s)", micros / 1_000_000, micros % 1_000_000, msg.size());
+
+    let mut frame = msg.first();
+    while let Some(f) = frame {
+        let hex = f.strhex().unwrap_or_else(|_| Ok(String::new())).unwrap_or_default();
+        println!("  [{:03}] {}", f.size(), hex);
+        frame = msg.next();
+    }
+}
@@ -0,0 +1,141 @@
+//! Record every message received on a socket to a capture file, for
+//! later playback with czmq-replay.
+
+extern crate czmq;
+
+mod common;
+
+use czmq::{ZMsg, ZMsgFileWriter, ZProxy, ZSock, ZSys};
+use std::{env, process};
+
+const USAGE: &'static str = "\
+Usage: czmq-capture [options] <type> <endpoints> <file>
+       czmq-capture [options] --tap <frontend-type> <frontend-endpoints> <backend-type> <backend-endpoints> <file>
+
+Capture from a socket of <type> (e.g. sub, pull or dealer) attached to
+<endpoints>, or with --tap run a proxy between a frontend and backend
+and capture everything that passes through it.
+
+Endpoints are comma-separated. '@' binds and '>' connects; capture
+sockets connect by default and proxy sockets bind by default.
+
+Options:
+  -s, --subscribe <prefix>  Subscribe a SUB socket to <prefix> (default: everything)
+  -n, --count <n>           Stop after <n> messages
+  -d, --dump                Print each message to stdout as it's captured
+  -h, --help                Print this message";
+
+const TAP_ENDPOINT: &'static str = "inproc://czmq-capture-tap";
+
+struct Options {
+    tap: bool,
+    subscribe: Vec<String>,
+    count: Option<u64>,
+    dump: bool,
+    args: Vec<String>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("czmq-capture: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let opts = try!(parse_args());
+    ZSys::init();
+
+    // Keep the proxy alive for as long as we're capturing
+    let mut _proxy = None;
+
+    let (mut sock, file) = if opts.tap {
+        if opts.args.len() != 5 {
+            return Err(format!("Wrong number of arguments\n\n{}", USAGE));
+        }
+
+        let sock = try!(ZSock::new_pull(&format!("@{}", TAP_ENDPOINT)).map_err(|e| e.to_string()));
+        let proxy = try!(ZProxy::new().map_err(|e| e.to_string()));
+        try!(proxy.set_frontend(try!(sock_type(&opts.args[0])), &opts.args[1]).map_err(|e| e.to_string()));
+        try!(proxy.set_backend(try!(sock_type(&opts.args[2])), &opts.args[3]).map_err(|e| e.to_string()));
+        try!(proxy.set_capture(TAP_ENDPOINT).map_err(|e| e.to_string()));
+        _proxy = Some(proxy);
+
+        (sock, &opts.args[4])
+    } else {
+        if opts.args.len() != 3 {
+            return Err(format!("Wrong number of arguments\n\n{}", USAGE));
+        }
+
+        let mut builder = ZSock::builder(try!(sock_type(&opts.args[0]))).attach(&opts.args[1], false);
+        if opts.subscribe.is_empty() {
+            builder = builder.subscribe("");
+        }
+        for prefix in &opts.subscribe {
+            builder = builder.subscribe(prefix);
+        }
+
+        (try!(builder.build().map_err(|e| e.to_string())), &opts.args[2])
+    };
+
+    let mut writer = try!(ZMsgFileWriter::create(file).map_err(|e| e.to_string()));
+    let mut captured = 0;
+
+    while opts.count.map_or(true, |c| captured < c) {
+        let msg = match ZMsg::recv(&mut sock) {
+            Ok(msg) => msg,
+            // Interrupted by Ctrl-C
+            Err(_) if ZSys::is_interrupted() => break,
+            Err(e) => return Err(e.to_string()),
+        };
+        let micros = common::now_micros();
+
+        if opts.dump {
+            common::dump(micros, &msg);
+        }
+
+        try!(msg.prepend(common::timestamp_frame(micros)).map_err(|e| e.to_string()));
+        try!(writer.write(&msg).map_err(|e| e.to_string()));
+        // Flush as we go, so that a killed capture is still useful
+        try!(writer.flush().map_err(|e| e.to_string()));
+        captured += 1;
+    }
+
+    eprintln!("czmq-capture: captured {} message(s)", captured);
+    Ok(())
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        tap: false,
+        subscribe: Vec::new(),
+        count: None,
+        dump: false,
+        args: Vec::new(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "--tap" => opts.tap = true,
+            "-s" | "--subscribe" => opts.subscribe.push(try!(args.next().ok_or("Missing prefix for --subscribe"))),
+            "-n" | "--count" => {
+                let count = try!(args.next().ok_or("Missing value for --count"));
+                opts.count = Some(try!(count.parse().map_err(|_| format!("Invalid count: {}", count))));
+            },
+            "-d" | "--dump" => opts.dump = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}\n\n{}", arg, USAGE)),
+            _ => opts.args.push(arg),
+        }
+    }
+
+    Ok(opts)
+}
+
+fn sock_type(name: &str) -> Result<czmq::SocketType, String> {
+    common::parse_sock_type(name).ok_or(format!("Unknown socket type: {}", name))
+}
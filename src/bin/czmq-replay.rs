@@ -0,0 +1,135 @@
+//! Resend the messages in a czmq-capture file to an endpoint.
+
+extern crate czmq;
+
+mod common;
+
+use czmq::{ZMsgFileReader, ZSock, ZSys};
+use std::{env, process, thread};
+use std::time::{Duration, Instant};
+
+const USAGE: &'static str = "\
+Usage: czmq-replay [options] <file> <endpoints>
+
+Send each message in a capture file from a socket attached to
+<endpoints>, with the same spacing as when it was captured.
+
+Endpoints are comma-separated. '@' binds and '>' connects; the default
+is to connect.
+
+Options:
+  -t, --type <type>   Socket type to send from (default: push)
+  -s, --speed <n>     Replay <n> times faster than captured, e.g. 0.5 for half speed
+  -f, --fast          Send as fast as possible, ignoring the captured timing
+  -w, --wait <ms>     Wait before sending, e.g. for subscribers to connect
+  -d, --dump          Print each message to stdout as it's sent
+  -h, --help          Print this message";
+
+struct Options {
+    sock_type: czmq::SocketType,
+    speed: f64,
+    fast: bool,
+    wait: u64,
+    dump: bool,
+    args: Vec<String>,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("czmq-replay: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let opts = try!(parse_args());
+
+    if opts.args.len() != 2 {
+        return Err(format!("Wrong number of arguments\n\n{}", USAGE));
+    }
+
+    ZSys::init();
+
+    let reader = try!(ZMsgFileReader::open(&opts.args[0]).map_err(|e| e.to_string()));
+    let mut sock = try!(ZSock::builder(opts.sock_type)
+        .attach(&opts.args[1], false)
+        .build()
+        .map_err(|e| e.to_string()));
+
+    thread::sleep(Duration::from_millis(opts.wait));
+
+    let mut first = None;
+    let start = Instant::now();
+    let mut sent = 0;
+
+    for msg in reader {
+        let msg = try!(msg.map_err(|e| e.to_string()));
+        let micros = try!(common::pop_timestamp(&msg).ok_or("Not a czmq-capture file"));
+
+        if !opts.fast {
+            let first = *first.get_or_insert(micros);
+            let offset = Duration::from_micros(((micros.saturating_sub(first)) as f64 / opts.speed) as u64);
+            let elapsed = start.elapsed();
+
+            if offset > elapsed {
+                thread::sleep(offset - elapsed);
+            }
+        }
+
+        if ZSys::is_interrupted() {
+            break;
+        }
+
+        if opts.dump {
+            common::dump(micros, &msg);
+        }
+
+        try!(msg.send(&mut sock).map_err(|e| e.to_string()));
+        sent += 1;
+    }
+
+    eprintln!("czmq-replay: sent {} message(s)", sent);
+    Ok(())
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        sock_type: czmq::SocketType::PUSH,
+        speed: 1.0,
+        fast: false,
+        wait: 0,
+        dump: false,
+        args: Vec::new(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "-t" | "--type" => {
+                let name = try!(args.next().ok_or("Missing value for --type"));
+                opts.sock_type = try!(common::parse_sock_type(&name).ok_or(format!("Unknown socket type: {}", name)));
+            },
+            "-s" | "--speed" => {
+                let speed = try!(args.next().ok_or("Missing value for --speed"));
+                opts.speed = match speed.parse() {
+                    Ok(s) if s > 0.0 => s,
+                    _ => return Err(format!("Invalid speed: {}", speed)),
+                };
+            },
+            "-f" | "--fast" => opts.fast = true,
+            "-w" | "--wait" => {
+                let wait = try!(args.next().ok_or("Missing value for --wait"));
+                opts.wait = try!(wait.parse().map_err(|_| format!("Invalid wait: {}", wait)));
+            },
+            "-d" | "--dump" => opts.dump = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}\n\n{}", arg, USAGE)),
+            _ => opts.args.push(arg),
+        }
+    }
+
+    Ok(opts)
+}
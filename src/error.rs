@@ -4,18 +4,22 @@ use std::borrow::Borrow;
 use std::convert::{From, Into};
 use std::error;
 use std::ffi::NulError;
+use std::io;
 use std::fmt::{Display, Formatter, Result};
 use std::str::Utf8Error;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ErrorKind {
+    Codec,
     InvalidArg,
     InvalidPath,
     InvalidPtr,
+    Io,
     MissingFrame,
     NonZero,
     NullPtr,
     StringConversion,
+    Timeout,
 }
 
 #[derive(Debug)]
@@ -32,18 +36,25 @@ impl Error {
             cause: error.into(),
         }
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match self.kind {
+            ErrorKind::Codec => write!(f, "Could not encode or decode payload: {}", self.cause),
             ErrorKind::InvalidArg => write!(f, "Argument was invalid: {}", self.cause),
             ErrorKind::InvalidPath => write!(f, "File path was invalid: {}", self.cause),
             ErrorKind::InvalidPtr => write!(f, "CZMQ returned invalid pointer: {}", self.cause),
+            ErrorKind::Io => write!(f, "I/O error: {}", self.cause),
             ErrorKind::MissingFrame => write!(f, "Missing frame in CZMQ reply: {}", self.cause),
             ErrorKind::NonZero => write!(f, "CZMQ returned non-zero code: {}", self.cause),
             ErrorKind::NullPtr => write!(f, "CZMQ returned null pointer: {}", self.cause),
             ErrorKind::StringConversion => write!(f, "String conversion error: {}", self.cause),
+            ErrorKind::Timeout => write!(f, "Timed out: {}", self.cause),
         }
     }
 }
@@ -51,19 +62,33 @@ impl Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match self.kind {
+            ErrorKind::Codec => "Could not encode or decode payload",
             ErrorKind::InvalidArg => "Argument was invalid",
             ErrorKind::InvalidPath => "File path was invalid",
             ErrorKind::InvalidPtr => "CZMQ returned invalid pointer",
+            ErrorKind::Io => "I/O error",
             ErrorKind::MissingFrame => "Missing frame in CZMQ reply",
             ErrorKind::NonZero => "CZMQ returned non-zero code",
             ErrorKind::NullPtr => "CZMQ returned null pointer",
             ErrorKind::StringConversion => "Could not convert string to required type",
+            ErrorKind::Timeout => "Timed out",
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         Some(self.cause.borrow())
     }
+
+    // Lets callers downcast to the module error, e.g. ZAuthError
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        Some(&*self.cause)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(ioe: io::Error) -> Error {
+        Error::new(ErrorKind::Io, ioe)
+    }
 }
 
 impl From<NulError> for Error {
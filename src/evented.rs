@@ -0,0 +1,242 @@
+//! Module: czmq-evented
+//!
+//! mio integration. ZeroMQ sockets can't be polled directly, but each
+//! one exposes a ZMQ_FD that becomes readable whenever its ZMQ_EVENTS
+//! may have changed. That fd is edge-triggered, so after every wakeup
+//! you must keep handling the socket until ZMQ_EVENTS says it has
+//! nothing left, or you'll never hear about the remaining messages.
+//! `EventedSock::drain()` does this for you.
+
+use {czmq_sys, Error, ErrorKind, Result, Sockish, ZActor, ZSock, ZSockEvents};
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+use mio::unix::EventedFd;
+use std::io;
+use std::os::unix::io::RawFd;
+use zsock::ZSockError;
+
+/// Makes any `Sockish` registrable with a `mio::Poll`.
+///
+/// Regardless of the `Ready` interest you register, the socket is
+/// always signalled as readable; check `events()` to see whether it
+/// can actually be read from or written to.
+pub struct EventedSock<S: Sockish> {
+    sock: S,
+    fd: RawFd,
+}
+
+impl<S: Sockish> EventedSock<S> {
+    pub fn new(mut sock: S) -> EventedSock<S> {
+        let fd = unsafe { czmq_sys::zsock_fd(sock.as_mut_ptr()) };
+
+        EventedSock {
+            sock: sock,
+            fd: fd,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sock
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sock
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sock
+    }
+
+    /// Reading ZMQ_EVENTS resets the fd, so call this before going
+    /// back to `Poll::poll()`.
+    pub fn events(&mut self) -> Result<ZSockEvents> {
+        let events = unsafe { czmq_sys::zsock_events(self.sock.as_mut_ptr()) };
+
+        if events == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(ZSockEvents::from_bits_truncate(events))
+        }
+    }
+
+    /// Call `handler` for as long as the socket has any of the
+    /// `interest` events pending, passing it those that are. The
+    /// handler must consume them (e.g. by receiving a message) or this
+    /// will loop forever.
+    pub fn drain<F>(&mut self, interest: ZSockEvents, mut handler: F) -> Result<()>
+        where F: FnMut(&mut S, ZSockEvents) -> Result<()>
+    {
+        loop {
+            let ready = try!(self.events()) & interest;
+
+            if ready.is_empty() {
+                return Ok(());
+            }
+
+            try!(handler(&mut self.sock, ready));
+        }
+    }
+}
+
+impl<S: Sockish> Evented for EventedSock<S> {
+    fn register(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.fd).register(poll, token, Ready::readable(), opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.fd).reregister(poll, token, Ready::readable(), opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.fd).deregister(poll)
+    }
+}
+
+// ZSock and ZActor can be registered directly, without the wrapper.
+
+impl Evented for ZSock {
+    fn register(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.fd()).register(poll, token, Ready::readable(), opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.fd()).reregister(poll, token, Ready::readable(), opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.fd()).deregister(poll)
+    }
+}
+
+impl Evented for ZActor {
+    fn register(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.sock().fd()).register(poll, token, Ready::readable(), opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, _interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.sock().fd()).reregister(poll, token, Ready::readable(), opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.sock().fd()).deregister(poll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mio::{Events, Poll, PollOpt, Ready, Token};
+    use mio::net::TcpListener;
+    use std::net::TcpStream;
+    use std::time::Duration;
+    use super::*;
+    use {ZActor, ZMsg, ZSock, ZSys, ZSOCK_POLLIN};
+
+    const SOCK: Token = Token(0);
+    const TCP: Token = Token(1);
+
+    #[test]
+    fn test_evented_sock() {
+        ZSys::init();
+
+        let pull = ZSock::new_pull("@inproc://evented_test_evented_sock").unwrap();
+        let push = ZSock::new_push(">inproc://evented_test_evented_sock").unwrap();
+        let mut pull = EventedSock::new(pull);
+
+        let poll = Poll::new().unwrap();
+        poll.register(&pull, SOCK, Ready::readable(), PollOpt::edge()).unwrap();
+
+        // A raw TCP source in the same loop
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        poll.register(&listener, TCP, Ready::readable(), PollOpt::edge()).unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        // Several messages arrive behind a single edge
+        for _ in 0..3 {
+            push.send_str("moo").unwrap();
+        }
+
+        let mut events = Events::with_capacity(8);
+        let mut received = 0;
+        let mut accepted = false;
+
+        pull.drain(ZSOCK_POLLIN, |sock, _| {
+            assert_eq!(sock.recv_str().unwrap().unwrap(), "moo");
+            received += 1;
+            Ok(())
+        }).unwrap();
+
+        while received < 3 || !accepted {
+            poll.poll(&mut events, Some(Duration::from_millis(1000))).unwrap();
+            assert!(!events.is_empty());
+
+            for event in events.iter() {
+                match event.token() {
+                    SOCK => pull.drain(ZSOCK_POLLIN, |sock, _| {
+                        assert_eq!(sock.recv_str().unwrap().unwrap(), "moo");
+                        received += 1;
+                        Ok(())
+                    }).unwrap(),
+                    TCP => accepted = listener.accept().is_ok(),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_evented_zsock() {
+        ZSys::init();
+
+        let mut pull = ZSock::new_pull("@inproc://evented_test_evented_zsock").unwrap();
+        let push = ZSock::new_push(">inproc://evented_test_evented_zsock").unwrap();
+
+        let poll = Poll::new().unwrap();
+        poll.register(&pull, SOCK, Ready::readable(), PollOpt::edge()).unwrap();
+        // Reset the fd before waiting on it
+        pull.events().unwrap();
+
+        push.send_str("moo").unwrap();
+
+        let mut events = Events::with_capacity(8);
+        poll.poll(&mut events, Some(Duration::from_millis(1000))).unwrap();
+        assert_eq!(events.iter().next().unwrap().token(), SOCK);
+        assert!(pull.events().unwrap().contains(ZSOCK_POLLIN));
+        assert_eq!(ZMsg::recv(&mut pull).unwrap().popstr().unwrap().unwrap(), "moo");
+
+        poll.deregister(&pull).unwrap();
+    }
+
+    #[test]
+    fn test_evented_zactor() {
+        ZSys::init();
+
+        let actor = ZActor::spawn(|mut pipe| {
+            pipe.send_str("moo").unwrap();
+            while let Ok(Some(_)) = ZActor::recv_cmd(&mut pipe) {}
+        }).unwrap();
+        let mut actor = EventedSock::new(actor);
+
+        let poll = Poll::new().unwrap();
+        poll.register(&actor, SOCK, Ready::readable(), PollOpt::edge()).unwrap();
+
+        let mut events = Events::with_capacity(8);
+        let mut received = Vec::new();
+
+        // The message may already be waiting, so drain before polling
+        actor.drain(ZSOCK_POLLIN, |actor, _| {
+            received.push(actor.recv().unwrap().popstr().unwrap().unwrap());
+            Ok(())
+        }).unwrap();
+
+        while received.is_empty() {
+            poll.poll(&mut events, Some(Duration::from_millis(1000))).unwrap();
+            assert!(!events.is_empty());
+
+            actor.drain(ZSOCK_POLLIN, |actor, _| {
+                received.push(actor.recv().unwrap().popstr().unwrap().unwrap());
+                Ok(())
+            }).unwrap();
+        }
+
+        assert_eq!(received, vec!["moo".to_string()]);
+    }
+}
@@ -1,57 +1,112 @@
 //! Module: czmq
 #![doc(html_root_url = "https://petehayes102.github.io/rust-czmq/")]
 
+#[cfg(feature = "serde")]
+extern crate bincode;
 #[macro_use]
 extern crate bitflags;
 extern crate czmq_sys;
+#[cfg(feature = "mio")]
+extern crate mio;
+#[cfg(feature = "stream")]
+extern crate futures_core;
+#[cfg(feature = "stream")]
+extern crate futures_sink;
+#[cfg(feature = "serde")]
+extern crate rmp_serde;
+#[cfg(feature = "serde")]
+#[cfg_attr(test, macro_use)]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
 #[cfg(test)]
 extern crate tempdir;
 #[cfg(test)]
 extern crate tempfile;
 extern crate zmq;
 
+#[cfg(all(unix, feature = "async"))]
+mod async_zsock;
 mod colander;
 mod error;
+#[cfg(all(unix, feature = "mio"))]
+mod evented;
+mod picture;
 mod socket;
 mod zactor;
+mod zap;
 mod zauth;
+mod zbeacon;
 mod zcert;
 mod zcertstore;
+#[cfg(feature = "serde")]
+mod zcodec;
 mod zframe;
+mod zgossip;
 mod zhashx;
 mod zlist;
+mod zloop;
 mod zmonitor;
 mod zmsg;
+#[macro_use]
 mod zpoller;
+mod zproxy;
 mod zsock;
 mod zsys;
 
+#[cfg(all(unix, feature = "async"))]
+pub use async_zsock::{AsyncZSock, ZSockRecv, ZSockSend};
 pub use colander::Colander;
 pub use czmq_sys::zcertstore_t as ZCertStoreRaw;
 pub use error::{Error, ErrorKind};
-pub use zactor::ZActor;
-pub use zauth::ZAuth;
+#[cfg(all(unix, feature = "mio"))]
+pub use evented::EventedSock;
+pub use picture::{BinaryArg, FromBinaryArg, FromPictureArg, PictureArg, PictureError, RecvBinaryPicture, RecvPicture, SendBinaryPicture, SendPicture};
+pub use zactor::{pop_cmd_str, ZActor, ZActorError, ZActorHandler, ZActorMessage};
+pub use zap::{ZAuthPolicy, ZapEvent, ZapHandler, ZapLog, ZapMechanism, ZapReply, ZapRequest, ZPlainUsers};
+pub use zauth::{ZAuth, ZAuthConfig, ZAuthError, ZAuthMode};
+pub use zbeacon::ZBeacon;
 pub use zcert::ZCert;
 pub use zcertstore::ZCertStore;
+#[cfg(feature = "serde")]
+pub use zcodec::{Bincode, Json, MsgPack, ZCodec};
 pub use zframe::{ZFrame, ZFRAME_MORE, ZFRAME_REUSE, ZFRAME_DONTWAIT};
+pub use zgossip::{ZGossip, ZGossipEvent, ZGossipMsg, ZGossipMsgId};
 pub use zhashx::ZHashX;
 pub use zlist::ZList;
+pub use zloop::{ZLoop, ZLoopAction, ZLoopReader, ZLoopTicket, ZLoopTimer};
 pub use zmonitor::{ZMonitor, ZMonitorEvents};
 pub use zmq::{Mechanism, SocketType};
-pub use zmsg::ZMsg;
-pub use zpoller::ZPoller;
-pub use zsock::ZSock;
+pub use zmsg::{ZMsg, ZMsgFileReader, ZMsgFileWriter};
+pub use zpoller::{ZPoller, ZPollerEvent, ZPollerToken};
+pub use zproxy::{ZProxy, ZProxySide};
+pub use zsock::{ZSock, ZSockBuilder, ZSockEvents, ZSOCK_POLLIN, ZSOCK_POLLOUT, ZSOCK_POLLERR};
+#[cfg(feature = "draft")]
+pub use zsock::DraftSocketType;
 pub use zsys::ZSys;
 
 use std::os::raw::c_void;
 use std::result;
+use std::time::Duration;
 
 pub type Result<T> = result::Result<T, Error>;
 
 pub trait RawInterface<P> {
-    unsafe fn from_raw(ptr: *mut P, owned: bool) -> Self;
+    unsafe fn from_raw(ptr: *mut P, owned: bool) -> Self where Self: Sized;
     fn into_raw(self) -> *mut P;
     fn as_mut_ptr(&mut self) -> *mut P;
 }
 
 pub trait Sockish : RawInterface<c_void> {}
+
+// Negative intervals mean "unset" or "forever" to libzmq and CZMQ, so
+// durations too long for an i32 saturate rather than wrap.
+fn duration_ms(duration: Duration) -> i32 {
+    let ms = duration.as_secs().saturating_mul(1000).saturating_add((duration.subsec_nanos() / 1_000_000) as u64);
+
+    if ms > i32::MAX as u64 {
+        i32::MAX
+    } else {
+        ms as i32
+    }
+}
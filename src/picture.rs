@@ -0,0 +1,990 @@
+//! Module: czmq-picture
+
+use {Error, ErrorKind, Result, ZFrame, ZMsg};
+use std::{error, fmt, mem};
+use std::collections::HashMap;
+use std::os::raw::c_void;
+
+// Pictures describe a multi-frame message one character per frame,
+// the same way as zsock_send()/zsock_recv(). Each Rust argument maps
+// to exactly one character, so 'b' takes a single byte slice rather
+// than a pointer and size, and 'z' takes a ().
+//
+//   i = i32           decimal string
+//   u = u32           decimal string
+//   1 = u8            decimal string
+//   2 = u16           decimal string
+//   4 = u32           decimal string
+//   8 = u64           decimal string
+//   s = str/String    string
+//   b = [u8]/Vec<u8>  raw bytes
+//   c = [u8]/Vec<u8>  raw bytes (a zchunk on the C side)
+//   f = ZFrame        one frame
+//   h = HashMap       zhashx_pack() of string keys and values
+//   l = Vec<String>   zlistx_pack() of strings
+//   m = ZMsg          all remaining frames
+//   p = *mut c_void   the pointer value, only meaningful over inproc
+//   z = ()            empty frame
+
+// Binary pictures, as used by zsock_bsend()/zsock_brecv(), pack
+// everything except frames and messages into a single frame, with
+// integers and lengths in network order. Frames and messages follow
+// as extra frames.
+//
+//   1 = u8            1 byte
+//   2 = u16           2 bytes
+//   4 = u32           4 bytes
+//   8 = u64           8 bytes
+//   s = str/String    1-byte length, then data
+//   S = str/String    4-byte length, then data
+//   c = [u8]/Vec<u8>  4-byte length, then data
+//   f = ZFrame        extra frame
+//   m = ZMsg          all remaining frames
+
+/// A value that can be added to a picture message.
+pub trait PictureArg {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()>;
+}
+
+/// A value that can be popped from a picture message.
+pub trait FromPictureArg: Sized {
+    /// The character used when receiving without an explicit picture.
+    fn picture_char() -> char;
+    fn pop_from(c: char, msg: &ZMsg) -> Result<Self>;
+}
+
+/// A tuple of a picture followed by its arguments.
+pub trait SendPicture {
+    fn to_msg(&self) -> Result<ZMsg>;
+}
+
+/// A tuple of values to be received from a picture message.
+pub trait RecvPicture: Sized {
+    fn picture() -> String;
+    fn from_msg(picture: &str, msg: &ZMsg) -> Result<Self>;
+}
+
+/// A value that can be packed into a binary picture. Frames and
+/// messages go into `frames` rather than `data`.
+pub trait BinaryArg {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, frames: &ZMsg) -> Result<()>;
+}
+
+/// A value that can be unpacked from a binary picture.
+pub trait FromBinaryArg: Sized {
+    fn bdecode(c: char, data: &mut &[u8], frames: &ZMsg) -> Result<Self>;
+}
+
+/// A tuple of a binary picture followed by its arguments.
+pub trait SendBinaryPicture {
+    fn to_bmsg(&self) -> Result<ZMsg>;
+}
+
+/// A tuple of values to be unpacked from a binary picture.
+pub trait RecvBinaryPicture: Sized {
+    fn from_bmsg(picture: &str, msg: &ZMsg) -> Result<Self>;
+}
+
+impl<'a, T: PictureArg + ?Sized> PictureArg for &'a T {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        (**self).add_to(c, msg)
+    }
+}
+
+impl PictureArg for i32 {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'i' => msg.addstr(&self.to_string()),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl FromPictureArg for i32 {
+    fn picture_char() -> char {
+        'i'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<i32> {
+        match c {
+            'i' => pop_num(c, msg),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for u32 {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'u' | '4' => msg.addstr(&self.to_string()),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl FromPictureArg for u32 {
+    fn picture_char() -> char {
+        'u'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<u32> {
+        match c {
+            'u' | '4' => pop_num(c, msg),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+macro_rules! number_arg {
+    ($t:ty, $c:expr) => {
+        impl PictureArg for $t {
+            fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+                match c {
+                    $c => msg.addstr(&self.to_string()),
+                    _ => Err(mismatch(c)),
+                }
+            }
+        }
+
+        impl FromPictureArg for $t {
+            fn picture_char() -> char {
+                $c
+            }
+
+            fn pop_from(c: char, msg: &ZMsg) -> Result<$t> {
+                match c {
+                    $c => pop_num(c, msg),
+                    _ => Err(mismatch(c)),
+                }
+            }
+        }
+    }
+}
+
+number_arg!(u8, '1');
+number_arg!(u16, '2');
+number_arg!(u64, '8');
+
+impl PictureArg for str {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            's' => msg.addstr(self),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for String {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        self.as_str().add_to(c, msg)
+    }
+}
+
+impl FromPictureArg for String {
+    fn picture_char() -> char {
+        's'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<String> {
+        match c {
+            's' => match String::from_utf8(try!(pop_bytes(c, msg))) {
+                Ok(s) => Ok(s),
+                Err(e) => Err(Error::new(ErrorKind::StringConversion, e)),
+            },
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for [u8] {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'b' | 'c' => msg.addbytes(self),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for Vec<u8> {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        self.as_slice().add_to(c, msg)
+    }
+}
+
+impl FromPictureArg for Vec<u8> {
+    fn picture_char() -> char {
+        'b'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<Vec<u8>> {
+        match c {
+            'b' | 'c' => pop_bytes(c, msg),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for ZFrame {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'f' => msg.append(try!(self.dup())),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl FromPictureArg for ZFrame {
+    fn picture_char() -> char {
+        'f'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<ZFrame> {
+        match c {
+            'f' => pop_frame(c, msg),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for ZMsg {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        if c != 'm' {
+            return Err(mismatch(c));
+        }
+
+        let mut frame = self.first();
+        while let Some(f) = frame {
+            try!(msg.append(try!(f.dup())));
+            frame = self.next();
+        }
+
+        Ok(())
+    }
+}
+
+impl FromPictureArg for ZMsg {
+    fn picture_char() -> char {
+        'm'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<ZMsg> {
+        if c != 'm' {
+            return Err(mismatch(c));
+        }
+
+        let rest = ZMsg::new();
+        while let Some(frame) = msg.pop() {
+            try!(rest.append(frame));
+        }
+
+        Ok(rest)
+    }
+}
+
+impl PictureArg for HashMap<String, String> {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        if c != 'h' {
+            return Err(mismatch(c));
+        }
+
+        let mut packed = Vec::new();
+        packed.extend_from_slice(&(self.len() as u32).to_be_bytes());
+
+        for (key, value) in self {
+            if key.len() > 255 {
+                return Err(Error::new(ErrorKind::InvalidArg, PictureError::BadArg(c)));
+            }
+            packed.push(key.len() as u8);
+            packed.extend_from_slice(key.as_bytes());
+            packed.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            packed.extend_from_slice(value.as_bytes());
+        }
+
+        msg.addbytes(&packed)
+    }
+}
+
+impl FromPictureArg for HashMap<String, String> {
+    fn picture_char() -> char {
+        'h'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<HashMap<String, String>> {
+        if c != 'h' {
+            return Err(mismatch(c));
+        }
+
+        let packed = try!(pop_bytes(c, msg));
+        let mut reader = Unpacker { c: c, data: &packed };
+        let mut hash = HashMap::new();
+
+        for _ in 0..try!(reader.number4()) {
+            let key_len = try!(reader.take(1))[0] as usize;
+            let key = try!(reader.string(key_len));
+            let value_len = try!(reader.number4()) as usize;
+            hash.insert(key, try!(reader.string(value_len)));
+        }
+
+        Ok(hash)
+    }
+}
+
+impl PictureArg for [String] {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        if c != 'l' {
+            return Err(mismatch(c));
+        }
+
+        let mut packed = Vec::new();
+        packed.extend_from_slice(&(self.len() as u32).to_be_bytes());
+
+        for item in self {
+            packed.extend_from_slice(&(item.len() as u32).to_be_bytes());
+            packed.extend_from_slice(item.as_bytes());
+        }
+
+        msg.addbytes(&packed)
+    }
+}
+
+impl PictureArg for Vec<String> {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        self.as_slice().add_to(c, msg)
+    }
+}
+
+impl FromPictureArg for Vec<String> {
+    fn picture_char() -> char {
+        'l'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<Vec<String>> {
+        if c != 'l' {
+            return Err(mismatch(c));
+        }
+
+        let packed = try!(pop_bytes(c, msg));
+        let mut reader = Unpacker { c: c, data: &packed };
+        let mut list = Vec::new();
+
+        for _ in 0..try!(reader.number4()) {
+            let len = try!(reader.number4()) as usize;
+            list.push(try!(reader.string(len)));
+        }
+
+        Ok(list)
+    }
+}
+
+impl PictureArg for *mut c_void {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'p' => msg.addbytes(&(*self as usize).to_ne_bytes()),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl FromPictureArg for *mut c_void {
+    fn picture_char() -> char {
+        'p'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<*mut c_void> {
+        match c {
+            'p' => {
+                let mut buf = [0; mem::size_of::<usize>()];
+                try!(pop_fixed(c, msg, &mut buf));
+                Ok(usize::from_ne_bytes(buf) as *mut c_void)
+            },
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl PictureArg for () {
+    fn add_to(&self, c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'z' => msg.addbytes(&[]),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl FromPictureArg for () {
+    fn picture_char() -> char {
+        'z'
+    }
+
+    fn pop_from(c: char, msg: &ZMsg) -> Result<()> {
+        match c {
+            'z' => pop_fixed(c, msg, &mut []),
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl<'a, T: BinaryArg + ?Sized> BinaryArg for &'a T {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, frames: &ZMsg) -> Result<()> {
+        (**self).bencode(c, data, frames)
+    }
+}
+
+macro_rules! binary_int_arg {
+    ($t:ty, $c:expr, $size:expr) => {
+        impl BinaryArg for $t {
+            fn bencode(&self, c: char, data: &mut Vec<u8>, _: &ZMsg) -> Result<()> {
+                match c {
+                    $c => {
+                        data.extend_from_slice(&self.to_be_bytes());
+                        Ok(())
+                    },
+                    _ => Err(mismatch(c)),
+                }
+            }
+        }
+
+        impl FromBinaryArg for $t {
+            fn bdecode(c: char, data: &mut &[u8], _: &ZMsg) -> Result<$t> {
+                match c {
+                    $c => {
+                        let mut buf = [0; $size];
+                        buf.copy_from_slice(try!(take(c, data, $size)));
+                        Ok(<$t>::from_be_bytes(buf))
+                    },
+                    _ => Err(mismatch(c)),
+                }
+            }
+        }
+    }
+}
+
+binary_int_arg!(u8, '1', 1);
+binary_int_arg!(u16, '2', 2);
+binary_int_arg!(u32, '4', 4);
+binary_int_arg!(u64, '8', 8);
+
+impl BinaryArg for str {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, _: &ZMsg) -> Result<()> {
+        match c {
+            's' if self.len() > 255 => return Err(Error::new(ErrorKind::InvalidArg, PictureError::BadArg(c))),
+            's' => data.push(self.len() as u8),
+            'S' => data.extend_from_slice(&(self.len() as u32).to_be_bytes()),
+            _ => return Err(mismatch(c)),
+        }
+
+        data.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
+}
+
+impl BinaryArg for String {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, frames: &ZMsg) -> Result<()> {
+        self.as_str().bencode(c, data, frames)
+    }
+}
+
+impl FromBinaryArg for String {
+    fn bdecode(c: char, data: &mut &[u8], _: &ZMsg) -> Result<String> {
+        let len = match c {
+            's' => try!(take(c, data, 1))[0] as usize,
+            'S' => try!(take_number4(c, data)) as usize,
+            _ => return Err(mismatch(c)),
+        };
+
+        match String::from_utf8(try!(take(c, data, len)).to_vec()) {
+            Ok(s) => Ok(s),
+            Err(e) => Err(Error::new(ErrorKind::StringConversion, e)),
+        }
+    }
+}
+
+impl BinaryArg for [u8] {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, _: &ZMsg) -> Result<()> {
+        match c {
+            'c' => {
+                data.extend_from_slice(&(self.len() as u32).to_be_bytes());
+                data.extend_from_slice(self);
+                Ok(())
+            },
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl BinaryArg for Vec<u8> {
+    fn bencode(&self, c: char, data: &mut Vec<u8>, frames: &ZMsg) -> Result<()> {
+        self.as_slice().bencode(c, data, frames)
+    }
+}
+
+impl FromBinaryArg for Vec<u8> {
+    fn bdecode(c: char, data: &mut &[u8], _: &ZMsg) -> Result<Vec<u8>> {
+        match c {
+            'c' => {
+                let len = try!(take_number4(c, data)) as usize;
+                Ok(try!(take(c, data, len)).to_vec())
+            },
+            _ => Err(mismatch(c)),
+        }
+    }
+}
+
+impl BinaryArg for ZFrame {
+    fn bencode(&self, c: char, _: &mut Vec<u8>, frames: &ZMsg) -> Result<()> {
+        self.add_to(c, frames)
+    }
+}
+
+impl FromBinaryArg for ZFrame {
+    fn bdecode(c: char, _: &mut &[u8], frames: &ZMsg) -> Result<ZFrame> {
+        ZFrame::pop_from(c, frames)
+    }
+}
+
+impl BinaryArg for ZMsg {
+    fn bencode(&self, c: char, _: &mut Vec<u8>, frames: &ZMsg) -> Result<()> {
+        self.add_to(c, frames)
+    }
+}
+
+impl FromBinaryArg for ZMsg {
+    fn bdecode(c: char, _: &mut &[u8], frames: &ZMsg) -> Result<ZMsg> {
+        ZMsg::pop_from(c, frames)
+    }
+}
+
+fn take<'a>(c: char, data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(Error::new(ErrorKind::InvalidArg, PictureError::Truncated(c)));
+    }
+
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_number4(c: char, data: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(try!(take(c, data, 4)));
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn picture_chars(picture: &str, args: usize) -> Result<Vec<char>> {
+    let chars: Vec<char> = picture.chars().collect();
+
+    if chars.len() != args {
+        Err(Error::new(ErrorKind::InvalidArg, PictureError::Length { picture: chars.len(), args: args }))
+    } else {
+        Ok(chars)
+    }
+}
+
+macro_rules! picture_tuple {
+    ($len:expr; $($arg:ident $send:tt $recv:tt),+) => {
+        impl<'p, $($arg: PictureArg),+> SendPicture for (&'p str, $($arg,)+) {
+            fn to_msg(&self) -> Result<ZMsg> {
+                let chars = try!(picture_chars(self.0, $len));
+                let msg = ZMsg::new();
+                $(try!(self.$send.add_to(chars[$recv], &msg));)+
+                Ok(msg)
+            }
+        }
+
+        impl<$($arg: FromPictureArg),+> RecvPicture for ($($arg,)+) {
+            fn picture() -> String {
+                let mut picture = String::new();
+                $(picture.push($arg::picture_char());)+
+                picture
+            }
+
+            fn from_msg(picture: &str, msg: &ZMsg) -> Result<($($arg,)+)> {
+                let chars = try!(picture_chars(picture, $len));
+                Ok(($(try!($arg::pop_from(chars[$recv], msg)),)+))
+            }
+        }
+    }
+}
+
+macro_rules! binary_picture_tuple {
+    ($len:expr; $($arg:ident $send:tt $recv:tt),+) => {
+        impl<'p, $($arg: BinaryArg),+> SendBinaryPicture for (&'p str, $($arg,)+) {
+            fn to_bmsg(&self) -> Result<ZMsg> {
+                let chars = try!(picture_chars(self.0, $len));
+                let mut data = Vec::new();
+                let frames = ZMsg::new();
+                $(try!(self.$send.bencode(chars[$recv], &mut data, &frames));)+
+
+                try!(frames.pushbytes(&data));
+                Ok(frames)
+            }
+        }
+
+        impl<$($arg: FromBinaryArg),+> RecvBinaryPicture for ($($arg,)+) {
+            fn from_bmsg(picture: &str, msg: &ZMsg) -> Result<($($arg,)+)> {
+                let chars = try!(picture_chars(picture, $len));
+                let packed = try!(pop_bytes(chars[0], msg));
+                let mut data = &packed[..];
+                let values = ($(try!($arg::bdecode(chars[$recv], &mut data, msg)),)+);
+
+                if data.is_empty() {
+                    Ok(values)
+                } else {
+                    Err(Error::new(ErrorKind::InvalidArg, PictureError::Oversized(data.len())))
+                }
+            }
+        }
+    }
+}
+
+binary_picture_tuple!(1; A 1 0);
+binary_picture_tuple!(2; A 1 0, B 2 1);
+binary_picture_tuple!(3; A 1 0, B 2 1, C 3 2);
+binary_picture_tuple!(4; A 1 0, B 2 1, C 3 2, D 4 3);
+binary_picture_tuple!(5; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4);
+binary_picture_tuple!(6; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5);
+binary_picture_tuple!(7; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5, G 7 6);
+binary_picture_tuple!(8; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5, G 7 6, H 8 7);
+
+picture_tuple!(1; A 1 0);
+picture_tuple!(2; A 1 0, B 2 1);
+picture_tuple!(3; A 1 0, B 2 1, C 3 2);
+picture_tuple!(4; A 1 0, B 2 1, C 3 2, D 4 3);
+picture_tuple!(5; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4);
+picture_tuple!(6; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5);
+picture_tuple!(7; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5, G 7 6);
+picture_tuple!(8; A 1 0, B 2 1, C 3 2, D 4 3, E 5 4, F 6 5, G 7 6, H 8 7);
+
+fn mismatch(c: char) -> Error {
+    Error::new(ErrorKind::InvalidArg, PictureError::Mismatch(c))
+}
+
+fn pop_frame(c: char, msg: &ZMsg) -> Result<ZFrame> {
+    msg.pop().ok_or(Error::new(ErrorKind::MissingFrame, PictureError::MissingFrame(c)))
+}
+
+fn pop_bytes(c: char, msg: &ZMsg) -> Result<Vec<u8>> {
+    let frame = try!(pop_frame(c, msg));
+
+    match try!(frame.data()) {
+        Ok(s) => Ok(s.into_bytes()),
+        Err(b) => Ok(b),
+    }
+}
+
+fn pop_fixed(c: char, msg: &ZMsg, buf: &mut [u8]) -> Result<()> {
+    let bytes = try!(pop_bytes(c, msg));
+
+    if bytes.len() != buf.len() {
+        Err(Error::new(ErrorKind::InvalidArg, PictureError::BadFrame(c)))
+    } else {
+        buf.copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+fn pop_num<N: ::std::str::FromStr>(c: char, msg: &ZMsg) -> Result<N> {
+    match String::from_utf8(try!(pop_bytes(c, msg))).ok().and_then(|s| s.parse().ok()) {
+        Some(n) => Ok(n),
+        None => Err(Error::new(ErrorKind::InvalidArg, PictureError::BadFrame(c))),
+    }
+}
+
+// Reads the network-order fields of zhashx_pack() and zlistx_pack()
+// frames.
+struct Unpacker<'a> {
+    c: char,
+    data: &'a [u8],
+}
+
+impl<'a> Unpacker<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(Error::new(ErrorKind::InvalidArg, PictureError::BadFrame(self.c)));
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn number4(&mut self) -> Result<u32> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(try!(self.take(4)));
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        match String::from_utf8(try!(self.take(len)).to_vec()) {
+            Ok(s) => Ok(s),
+            Err(e) => Err(Error::new(ErrorKind::StringConversion, e)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PictureError {
+    BadArg(char),
+    BadFrame(char),
+    Length { picture: usize, args: usize },
+    Mismatch(char),
+    MissingFrame(char),
+    Oversized(usize),
+    Truncated(char),
+}
+
+impl fmt::Display for PictureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PictureError::BadArg(c) => write!(f, "Argument can't be encoded as picture character '{}'", c),
+            PictureError::BadFrame(c) => write!(f, "Frame doesn't match picture character '{}'", c),
+            PictureError::Length { picture, args } => write!(f, "Picture has {} characters but {} arguments", picture, args),
+            PictureError::Mismatch(c) => write!(f, "Picture character '{}' doesn't match the argument type", c),
+            PictureError::MissingFrame(c) => write!(f, "Missing frame for picture character '{}'", c),
+            PictureError::Oversized(n) => write!(f, "Binary picture frame has {} unread bytes", n),
+            PictureError::Truncated(c) => write!(f, "Binary picture frame is truncated at picture character '{}'", c),
+        }
+    }
+}
+
+impl error::Error for PictureError {
+    fn description(&self) -> &str {
+        match *self {
+            PictureError::BadArg(_) => "Argument can't be encoded as picture character",
+            PictureError::BadFrame(_) => "Frame doesn't match picture character",
+            PictureError::Length { .. } => "Picture length doesn't match the number of arguments",
+            PictureError::Mismatch(_) => "Picture character doesn't match the argument type",
+            PictureError::MissingFrame(_) => "Missing frame for picture character",
+            PictureError::Oversized(_) => "Binary picture frame has unread bytes",
+            PictureError::Truncated(_) => "Binary picture frame is truncated",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use czmq_sys;
+    use std::collections::HashMap;
+    use std::ffi::{CStr, CString};
+    use std::{ptr, slice};
+    use std::os::raw::{c_char, c_void};
+    use super::*;
+    use {RawInterface, ZFrame, ZMsg, ZSock, ZSys};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut hash = HashMap::new();
+        hash.insert("key".to_string(), "value".to_string());
+        let list = vec!["moo".to_string(), "cow".to_string()];
+
+        let msg = ("iu1248sb", -1i32, 2u32, 3u8, 4u16, 5u32, 6u64, "seven", &b"eight"[..])
+            .to_msg().unwrap();
+        assert!(msg.first().unwrap().streq("-1"));
+        for expected in &["2", "3", "4", "5", "6"] {
+            assert!(msg.next().unwrap().streq(expected));
+        }
+        let tail = ("hlz", &hash, &list, ()).to_msg().unwrap();
+        msg.addmsg(tail).unwrap();
+        assert_eq!(msg.size(), 9);
+
+        let (i, u, one, two, four, eight, s, b): (i32, u32, u8, u16, u32, u64, String, Vec<u8>) =
+            RecvPicture::from_msg("iu1248sb", &msg).unwrap();
+        assert_eq!((i, u, one, two, four, eight), (-1, 2, 3, 4, 5, 6));
+        assert_eq!(s, "seven");
+        assert_eq!(b, b"eight");
+
+        let rest = msg.popmsg().unwrap();
+        let (h, l, z): (HashMap<String, String>, Vec<String>, ()) =
+            RecvPicture::from_msg(&<(HashMap<String, String>, Vec<String>, ())>::picture(), &rest).unwrap();
+        assert_eq!(h, hash);
+        assert_eq!(l, list);
+        assert_eq!(z, ());
+    }
+
+    #[test]
+    fn test_frames() {
+        let frame = ZFrame::from("frame").unwrap();
+        let inner = ZMsg::new();
+        inner.addstr("moo").unwrap();
+        inner.addstr("cow").unwrap();
+        let ptr = 0xdead as *mut c_void;
+
+        let msg = ("pfm", ptr, &frame, &inner).to_msg().unwrap();
+        let (p, f, m): (*mut c_void, ZFrame, ZMsg) = RecvPicture::from_msg("pfm", &msg).unwrap();
+        assert_eq!(p, ptr);
+        assert!(f.streq("frame"));
+        assert_eq!(m.size(), 2);
+        assert_eq!(msg.size(), 0);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(("ss", "moo").to_msg().is_err());
+        assert!(("i", "moo").to_msg().is_err());
+
+        let msg = ("s", "moo").to_msg().unwrap();
+        let result: Result<(i32,)> = RecvPicture::from_msg("i", &msg);
+        assert!(result.is_err());
+
+        let result: Result<(String,)> = RecvPicture::from_msg("s", &ZMsg::new());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let frame = ZFrame::from("frame").unwrap();
+        let msg = ("1248sScf", 1u8, 2u16, 4u32, 8u64, "short", "long", &b"chunk"[..], &frame).to_bmsg().unwrap();
+        assert_eq!(msg.size(), 2);
+        assert_eq!(msg.first().unwrap().size(), 1 + 2 + 4 + 8 + 6 + 8 + 9);
+
+        let (one, two, four, eight, s, l, c, f): (u8, u16, u32, u64, String, String, Vec<u8>, ZFrame) =
+            RecvBinaryPicture::from_bmsg("1248sScf", &msg).unwrap();
+        assert_eq!((one, two, four, eight), (1, 2, 4, 8));
+        assert_eq!(s, "short");
+        assert_eq!(l, "long");
+        assert_eq!(c, b"chunk");
+        assert!(f.streq("frame"));
+    }
+
+    #[test]
+    fn test_binary_errors() {
+        let long = String::from_utf8(vec![b'x'; 256]).unwrap();
+        assert!(("s", &long).to_bmsg().is_err());
+
+        // Truncated
+        let msg = ("4", 1u32).to_bmsg().unwrap();
+        let result: Result<(u64,)> = RecvBinaryPicture::from_bmsg("8", &msg);
+        assert!(result.is_err());
+
+        // Oversized
+        let msg = ("4", 1u32).to_bmsg().unwrap();
+        let result: Result<(u16,)> = RecvBinaryPicture::from_bmsg("2", &msg);
+        assert!(result.is_err());
+
+        // String length runs past the end of the frame
+        let msg = ZMsg::new();
+        msg.addbytes(&[10, b'x']).unwrap();
+        let result: Result<(String,)> = RecvBinaryPicture::from_bmsg("s", &msg);
+        assert!(result.is_err());
+
+        // Missing frame
+        let msg = ("1", 1u8).to_bmsg().unwrap();
+        let result: Result<(u8, ZFrame)> = RecvBinaryPicture::from_bmsg("1f", &msg);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bsend_to_c() {
+        ZSys::init();
+
+        let mut server = ZSock::new_pull("inproc://picture_test_bsend_to_c").unwrap();
+        let client = ZSock::new_push("inproc://picture_test_bsend_to_c").unwrap();
+        client.bsend(("1248sS", 1u8, 2u16, 4u32, 8u64, "short", "long")).unwrap();
+
+        let mut one: u8 = 0;
+        let mut two: u16 = 0;
+        let mut four: u32 = 0;
+        let mut eight: u64 = 0;
+        let mut s: *mut c_char = ptr::null_mut();
+        let mut l: *mut c_char = ptr::null_mut();
+        let picture = CString::new("1248sS").unwrap();
+
+        unsafe {
+            let rc = czmq_sys::zsock_brecv(server.as_mut_ptr(), picture.as_ptr(),
+                                           &mut one, &mut two, &mut four, &mut eight, &mut s, &mut l);
+            assert_eq!(rc, 0);
+            assert_eq!((one, two, four, eight), (1, 2, 4, 8));
+            // brecv strings point into the socket's own buffer
+            assert_eq!(CStr::from_ptr(s).to_str().unwrap(), "short");
+            assert_eq!(CStr::from_ptr(l).to_str().unwrap(), "long");
+        }
+    }
+
+    #[test]
+    fn test_brecv_from_c() {
+        ZSys::init();
+
+        let server = ZSock::new_pull("inproc://picture_test_brecv_from_c").unwrap();
+        let mut client = ZSock::new_push("inproc://picture_test_brecv_from_c").unwrap();
+
+        let picture = CString::new("1248sS").unwrap();
+        let s = CString::new("short").unwrap();
+        let l = CString::new("long").unwrap();
+
+        let rc = unsafe {
+            czmq_sys::zsock_bsend(client.as_mut_ptr(), picture.as_ptr(),
+                                  1u32, 2u32, 4u32, 8u64, s.as_ptr(), l.as_ptr())
+        };
+        assert_eq!(rc, 0);
+
+        let (one, two, four, eight, s, l): (u8, u16, u32, u64, String, String) = server.brecv("1248sS").unwrap();
+        assert_eq!((one, two, four, eight), (1, 2, 4, 8));
+        assert_eq!(s, "short");
+        assert_eq!(l, "long");
+    }
+
+    #[test]
+    fn test_send_to_c() {
+        ZSys::init();
+
+        let mut server = ZSock::new_pull("inproc://picture_test_send_to_c").unwrap();
+        let client = ZSock::new_push("inproc://picture_test_send_to_c").unwrap();
+
+        let mut hash = HashMap::new();
+        hash.insert("key".to_string(), "value".to_string());
+        client.send_picture(("sbi48h", "moo", &b"cow"[..], 42, 7u32, 8u64, &hash)).unwrap();
+
+        let mut s: *mut c_char = ptr::null_mut();
+        let mut b: *mut u8 = ptr::null_mut();
+        let mut b_size: u64 = 0;
+        let mut i: i32 = 0;
+        let mut four: u32 = 0;
+        let mut eight: u64 = 0;
+        let mut h: *mut czmq_sys::zhashx_t = ptr::null_mut();
+        let picture = CString::new("sbi48h").unwrap();
+
+        unsafe {
+            let rc = czmq_sys::zsock_recv(server.as_mut_ptr(), picture.as_ptr(),
+                                          &mut s, &mut b, &mut b_size, &mut i, &mut four, &mut eight, &mut h);
+            assert_eq!(rc, 0);
+            assert_eq!(CStr::from_ptr(s).to_str().unwrap(), "moo");
+            assert_eq!(slice::from_raw_parts(b, b_size as usize), b"cow");
+            assert_eq!(i, 42);
+            assert_eq!(four, 7);
+            assert_eq!(eight, 8);
+
+            let key = CString::new("key").unwrap();
+            let value = czmq_sys::zhashx_lookup(h, key.as_ptr() as *const c_void);
+            assert_eq!(CStr::from_ptr(value as *const c_char).to_str().unwrap(), "value");
+
+            czmq_sys::zstr_free(&mut s);
+            czmq_sys::zhashx_destroy(&mut h);
+        }
+    }
+
+    #[test]
+    fn test_recv_from_c() {
+        ZSys::init();
+
+        let server = ZSock::new_pull("inproc://picture_test_recv_from_c").unwrap();
+        let mut client = ZSock::new_push("inproc://picture_test_recv_from_c").unwrap();
+
+        let picture = CString::new("sbiu2z").unwrap();
+        let s = CString::new("moo").unwrap();
+        let b = b"cow";
+
+        let rc = unsafe {
+            czmq_sys::zsock_send(client.as_mut_ptr(), picture.as_ptr(),
+                                 s.as_ptr(), b.as_ptr(), b.len() as u64, -42i32, 42u32, 2u32)
+        };
+        assert_eq!(rc, 0);
+
+        let (s, b, i, u, two, z): (String, Vec<u8>, i32, u32, u16, ()) = server.recv_picture().unwrap();
+        assert_eq!(s, "moo");
+        assert_eq!(b, b"cow");
+        assert_eq!(i, -42);
+        assert_eq!(u, 42);
+        assert_eq!(two, 2);
+        assert_eq!(z, ());
+    }
+}
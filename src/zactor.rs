@@ -1,12 +1,52 @@
 //! Module: czmq-zactor
 
-use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZMsg, ZSock};
+use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZFrame, ZMsg, ZSock};
 use std::{error, fmt, ptr};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use zmq;
+
+type ActorTask = Box<FnOnce(ZSock) + Send>;
+
+// Sent back to the caller when the actor couldn't decode a command
+// or encode its reply.
+const ERROR_REPLY: &'static str = "$ERROR";
+
+// ZActor::call() sends this frame and a sequence number ahead of the
+// command, and the reply comes back behind the same two frames, so
+// that a reply which turns up after its call timed out can't be
+// mistaken for the answer to the next one, nor for any other message
+// from the actor.
+const CALL_FRAME: &'static str = "$CALL";
+
+static NEXT_CALL: AtomicU64 = AtomicU64::new(0);
+
+/// A command or reply that can be sent over an actor pipe.
+pub trait ZActorMessage: Sized {
+    fn encode(&self) -> Result<ZMsg>;
+    fn decode(msg: ZMsg) -> Result<Self>;
+}
+
+/// The actor side of a typed command protocol. Returning `None` from
+/// `handle()` sends no reply, which suits fire-and-forget commands
+/// sent with `ZActor::send_cmd()`. Commands that can't be decoded are
+/// only answered with an error if they were sent with `ZActor::call()`.
+pub trait ZActorHandler: Send + 'static {
+    type Command: ZActorMessage;
+    type Reply: ZActorMessage;
+
+    fn handle(&mut self, cmd: Self::Command) -> Option<Self::Reply>;
+}
 
 pub struct ZActor {
     zactor: *mut czmq_sys::zactor_t,
     owned: bool,
+    // Messages that arrived while call() was waiting for its reply
+    pending: RefCell<VecDeque<ZMsg>>,
 }
 
 unsafe impl Send for ZActor {}
@@ -29,10 +69,99 @@ impl ZActor {
             Ok(ZActor {
                 zactor: zactor,
                 owned: true,
+                pending: RefCell::new(VecDeque::new()),
+            })
+        }
+    }
+
+    /// Run a Rust closure as the actor's thread. The closure owns the
+    /// child end of the pipe and should return once `recv_cmd()`
+    /// yields `None`. The pipe is signalled before the closure is
+    /// called, and a panicking closure is caught so that it only
+    /// takes down the actor rather than the whole process.
+    pub fn spawn<F>(task: F) -> Result<ZActor>
+        where F: FnOnce(ZSock) + Send + 'static {
+        let task: Box<ActorTask> = Box::new(Box::new(task));
+        let args = Box::into_raw(task);
+        let zactor = unsafe { czmq_sys::zactor_new(spawn_shim, args as *mut c_void) };
+
+        if zactor == ptr::null_mut() {
+            // The actor thread never started, so the closure is
+            // still ours to clean up.
+            drop(unsafe { Box::from_raw(args) });
+            Err(Error::new(ErrorKind::NullPtr, ZActorError::Instantiate))
+        } else {
+            Ok(ZActor {
+                zactor: zactor,
+                owned: true,
+                pending: RefCell::new(VecDeque::new()),
             })
         }
     }
 
+    /// Run a `ZActorHandler` in a dispatch loop on the actor's thread
+    /// until the actor is destroyed.
+    pub fn spawn_handler<H: ZActorHandler>(handler: H) -> Result<ZActor> {
+        let mut handler = handler;
+
+        ZActor::spawn(move |mut pipe| {
+            while let Ok(Some(msg)) = ZActor::recv_cmd(&mut pipe) {
+                let seq = if msg.first().map_or(false, |f| f.streq(CALL_FRAME)) {
+                    msg.pop();
+                    msg.pop()
+                } else {
+                    None
+                };
+
+                let reply = match H::Command::decode(msg) {
+                    Ok(cmd) => match handler.handle(cmd) {
+                        Some(reply) => reply.encode(),
+                        None => continue,
+                    },
+                    Err(_) if seq.is_none() => continue,
+                    Err(e) => Err(e),
+                };
+
+                let reply = match reply {
+                    Ok(r) => r,
+                    Err(_) => {
+                        let r = ZMsg::new();
+                        if r.addstr(ERROR_REPLY).is_err() {
+                            break;
+                        }
+                        r
+                    },
+                };
+
+                if let Some(seq) = seq {
+                    let tagged = reply.prepend(seq)
+                        .and_then(|_| ZFrame::from(CALL_FRAME))
+                        .and_then(|frame| reply.prepend(frame));
+
+                    if tagged.is_err() {
+                        break;
+                    }
+                }
+
+                if reply.send(&mut pipe).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Receive the next command on an actor's pipe. Returns `None`
+    /// when the parent has sent `$TERM`, at which point the actor
+    /// should return.
+    pub fn recv_cmd(pipe: &mut ZSock) -> Result<Option<ZMsg>> {
+        let msg = try!(ZMsg::recv(pipe));
+
+        match msg.first() {
+            Some(ref frame) if frame.streq("$TERM") => Ok(None),
+            _ => Ok(Some(msg)),
+        }
+    }
+
     pub fn send(&self, msg: ZMsg) -> Result<()> {
         let rc = unsafe { czmq_sys::zactor_send(self.zactor, &mut msg.into_raw()) };
         if rc == -1 {
@@ -48,7 +177,59 @@ impl ZActor {
         self.send(msg)
     }
 
+    pub fn send_cmd<C: ZActorMessage>(&self, cmd: &C) -> Result<()> {
+        self.send(try!(cmd.encode()))
+    }
+
+    /// Send a command and wait for the actor's reply. A timeout of
+    /// `None` waits forever. The actor must be running a
+    /// `ZActorHandler`, which sends the reply back tagged with the
+    /// call it answers. Replies to earlier calls that timed out are
+    /// discarded. Any other messages from the actor are kept for
+    /// `recv()` and `recv_timeout()`, though not for readers of
+    /// `sock()`.
+    pub fn call<C, R>(&self, cmd: &C, timeout: Option<u32>) -> Result<R>
+        where C: ZActorMessage, R: ZActorMessage {
+        let seq = NEXT_CALL.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+        let msg = try!(cmd.encode());
+        try!(msg.prepend(try!(ZFrame::new(&seq))));
+        try!(msg.prepend(try!(ZFrame::from(CALL_FRAME))));
+        try!(self.send(msg));
+
+        let deadline = timeout.map(|t| Instant::now() + Duration::from_millis(t as u64));
+
+        loop {
+            let remaining = deadline.map(|d| {
+                let left = d.saturating_duration_since(Instant::now());
+                left.as_secs() as u32 * 1000 + left.subsec_millis()
+            });
+            let reply = try!(self.recv_pipe(remaining));
+
+            if !reply.first().map_or(false, |f| f.streq(CALL_FRAME)) {
+                self.pending.borrow_mut().push_back(reply);
+                continue;
+            }
+
+            match reply.next() {
+                Some(ref frame) if frame.as_bytes() == &seq[..] => (),
+                _ => continue,
+            }
+            reply.pop();
+            reply.pop();
+
+            return if reply.size() == 1 && reply.first().map_or(false, |f| f.streq(ERROR_REPLY)) {
+                Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand))
+            } else {
+                R::decode(reply)
+            };
+        }
+    }
+
     pub fn recv(&self) -> Result<ZMsg> {
+        if let Some(msg) = self.pending.borrow_mut().pop_front() {
+            return Ok(msg);
+        }
+
         let zmsg_ptr = unsafe { czmq_sys::zactor_recv(self.zactor) };
 
         if zmsg_ptr == ptr::null_mut() {
@@ -58,16 +239,55 @@ impl ZActor {
         }
     }
 
+    /// Receive a message, giving up after `timeout` milliseconds. A
+    /// timeout of `None` waits forever.
+    pub fn recv_timeout(&self, timeout: Option<u32>) -> Result<ZMsg> {
+        if let Some(msg) = self.pending.borrow_mut().pop_front() {
+            return Ok(msg);
+        }
+
+        self.recv_pipe(timeout)
+    }
+
+    fn recv_pipe(&self, timeout: Option<u32>) -> Result<ZMsg> {
+        let mut sock = self.sock();
+        let rcvtimeo = sock.rcvtimeo();
+        sock.set_rcvtimeo(timeout.map(|t| t as i32));
+        let msg = ZMsg::recv(&mut sock).map_err(|_| unsafe { czmq_sys::zmq_errno() });
+        sock.set_rcvtimeo(rcvtimeo);
+
+        msg.map_err(|errno| match zmq::Error::from_raw(errno) {
+            zmq::Error::EAGAIN => Error::new(ErrorKind::Timeout, ZActorError::Timeout),
+            e => Error::new(ErrorKind::NonZero, e),
+        })
+    }
+
     pub fn sock(&self) -> ZSock {
         unsafe { ZSock::from_raw(czmq_sys::zactor_sock(self.zactor) as *mut c_void, false) }
     }
 }
 
+unsafe extern "C" fn spawn_shim(pipe: *mut czmq_sys::zsock_t, args: *mut c_void) {
+    let task = Box::from_raw(args as *mut ActorTask);
+    let pipe = ZSock::from_raw(pipe as *mut c_void, false);
+
+    // zactor_new() blocks until the actor signals that it is ready
+    if pipe.signal(0).is_err() {
+        return;
+    }
+
+    // Unwinding across the FFI boundary is undefined behaviour, so
+    // catch any panic here. CZMQ signals the parent once we return,
+    // which keeps zactor_destroy() from blocking forever.
+    let _ = panic::catch_unwind(AssertUnwindSafe(move || task(pipe)));
+}
+
 impl RawInterface<c_void> for ZActor {
     unsafe fn from_raw(ptr: *mut c_void, owned: bool) -> ZActor {
         ZActor {
             zactor: ptr as *mut czmq_sys::zactor_t,
             owned: owned,
+            pending: RefCell::new(VecDeque::new()),
         }
     }
 
@@ -82,10 +302,23 @@ impl RawInterface<c_void> for ZActor {
 
 impl Sockish for ZActor {}
 
+// Pop the next string frame from a command message. Used by
+// ZActorMessage impls, for which a missing or non-UTF8 frame means a
+// malformed command.
+pub fn pop_cmd_str(msg: &ZMsg) -> Result<String> {
+    match msg.popstr() {
+        Some(Ok(s)) => Ok(s),
+        Some(Err(_)) => Err(Error::new(ErrorKind::StringConversion, ZActorError::BadCommand)),
+        None => Err(Error::new(ErrorKind::MissingFrame, ZActorError::BadCommand)),
+    }
+}
+
 #[derive(Debug)]
 pub enum ZActorError {
     Instantiate,
     CmdFailed,
+    BadCommand,
+    Timeout,
 }
 
 impl fmt::Display for ZActorError {
@@ -93,6 +326,8 @@ impl fmt::Display for ZActorError {
         match *self {
             ZActorError::Instantiate => write!(f, "Could not instantiate new ZActor struct"),
             ZActorError::CmdFailed => write!(f, "ZActor command failed"),
+            ZActorError::BadCommand => write!(f, "ZActor received a malformed command"),
+            ZActorError::Timeout => write!(f, "ZActor did not reply in time"),
         }
     }
 }
@@ -102,6 +337,207 @@ impl error::Error for ZActorError {
         match *self {
             ZActorError::Instantiate => "Could not instantiate new ZActor struct",
             ZActorError::CmdFailed => "ZActor command failed",
+            ZActorError::BadCommand => "ZActor received a malformed command",
+            ZActorError::Timeout => "ZActor did not reply in time",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use super::*;
+    use ZSys;
+
+    #[test]
+    fn test_spawn() {
+        ZSys::init();
+
+        let actor = ZActor::spawn(|mut pipe| {
+            while let Some(msg) = ZActor::recv_cmd(&mut pipe).unwrap() {
+                msg.send(&mut pipe).unwrap();
+            }
+        }).unwrap();
+
+        actor.send_str("Marco").unwrap();
+        assert_eq!(actor.recv().unwrap().popstr().unwrap().unwrap(), "Marco");
+    }
+
+    #[test]
+    fn test_spawn_early_exit() {
+        ZSys::init();
+
+        // Actor returns without waiting for $TERM. Dropping it must
+        // not block.
+        let actor = ZActor::spawn(|pipe| {
+            pipe.send_str("bye").unwrap();
+        }).unwrap();
+
+        assert_eq!(actor.sock().recv_str().unwrap().unwrap(), "bye");
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum CounterCmd {
+        Add(i32),
+        Get,
+        SlowGet(u64),
+    }
+
+    impl ZActorMessage for CounterCmd {
+        fn encode(&self) -> Result<ZMsg> {
+            let msg = ZMsg::new();
+            match *self {
+                CounterCmd::Add(n) => {
+                    try!(msg.addstr("ADD"));
+                    try!(msg.addstr(&n.to_string()));
+                },
+                CounterCmd::Get => try!(msg.addstr("GET")),
+                CounterCmd::SlowGet(ms) => {
+                    try!(msg.addstr("SLOWGET"));
+                    try!(msg.addstr(&ms.to_string()));
+                },
+            }
+            Ok(msg)
+        }
+
+        fn decode(msg: ZMsg) -> Result<CounterCmd> {
+            match try!(pop_cmd_str(&msg)).as_ref() {
+                "ADD" => match try!(pop_cmd_str(&msg)).parse() {
+                    Ok(n) => Ok(CounterCmd::Add(n)),
+                    Err(e) => Err(Error::new(ErrorKind::InvalidArg, e)),
+                },
+                "GET" => Ok(CounterCmd::Get),
+                "SLOWGET" => match try!(pop_cmd_str(&msg)).parse() {
+                    Ok(ms) => Ok(CounterCmd::SlowGet(ms)),
+                    Err(e) => Err(Error::new(ErrorKind::InvalidArg, e)),
+                },
+                _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Total(i32);
+
+    impl ZActorMessage for Total {
+        fn encode(&self) -> Result<ZMsg> {
+            let msg = ZMsg::new();
+            try!(msg.addstr(&self.0.to_string()));
+            Ok(msg)
+        }
+
+        fn decode(msg: ZMsg) -> Result<Total> {
+            match try!(pop_cmd_str(&msg)).parse() {
+                Ok(n) => Ok(Total(n)),
+                Err(e) => Err(Error::new(ErrorKind::InvalidArg, e)),
+            }
+        }
+    }
+
+    struct Counter {
+        total: i32,
+    }
+
+    impl ZActorHandler for Counter {
+        type Command = CounterCmd;
+        type Reply = Total;
+
+        fn handle(&mut self, cmd: CounterCmd) -> Option<Total> {
+            match cmd {
+                CounterCmd::Add(n) => {
+                    self.total += n;
+                    None
+                },
+                CounterCmd::Get => Some(Total(self.total)),
+                CounterCmd::SlowGet(ms) => {
+                    sleep(Duration::from_millis(ms));
+                    Some(Total(self.total))
+                },
+            }
         }
     }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let cmd = CounterCmd::decode(CounterCmd::Add(3).encode().unwrap()).unwrap();
+        assert_eq!(cmd, CounterCmd::Add(3));
+    }
+
+    #[test]
+    fn test_call() {
+        ZSys::init();
+
+        let actor = ZActor::spawn_handler(Counter { total: 0 }).unwrap();
+        actor.send_cmd(&CounterCmd::Add(40)).unwrap();
+        actor.send_cmd(&CounterCmd::Add(2)).unwrap();
+        let total: Total = actor.call(&CounterCmd::Get, Some(1000)).unwrap();
+        assert_eq!(total, Total(42));
+    }
+
+    #[test]
+    fn test_call_bad_command() {
+        ZSys::init();
+
+        let actor = ZActor::spawn_handler(Counter { total: 0 }).unwrap();
+        let result: Result<Total> = actor.call(&Total(1), Some(1000));
+        assert!(result.is_err());
+
+        // The actor survives a bad command
+        let total: Total = actor.call(&CounterCmd::Get, Some(1000)).unwrap();
+        assert_eq!(total, Total(0));
+    }
+
+    #[test]
+    fn test_call_timeout() {
+        ZSys::init();
+
+        let actor = ZActor::spawn_handler(Counter { total: 0 }).unwrap();
+        let result: Result<Total> = actor.call(&CounterCmd::Add(1), Some(100));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_call_late_reply() {
+        ZSys::init();
+
+        let actor = ZActor::spawn_handler(Counter { total: 1 }).unwrap();
+        let result: Result<Total> = actor.call(&CounterCmd::SlowGet(300), Some(50));
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Timeout);
+
+        // The late reply to SlowGet must not be taken for this one
+        actor.send_cmd(&CounterCmd::Add(1)).unwrap();
+        let total: Total = actor.call(&CounterCmd::Get, Some(1000)).unwrap();
+        assert_eq!(total, Total(2));
+    }
+
+    #[test]
+    fn test_call_keeps_other_messages() {
+        ZSys::init();
+
+        // Sends an event ahead of each reply
+        let actor = ZActor::spawn(|mut pipe| {
+            while let Some(msg) = ZActor::recv_cmd(&mut pipe).unwrap() {
+                pipe.send_str("event").unwrap();
+
+                let reply = Total(7).encode().unwrap();
+                msg.pop();
+                reply.prepend(msg.pop().unwrap()).unwrap();
+                reply.prepend(ZFrame::from(CALL_FRAME).unwrap()).unwrap();
+                reply.send(&mut pipe).unwrap();
+            }
+        }).unwrap();
+
+        let total: Total = actor.call(&CounterCmd::Get, Some(1000)).unwrap();
+        assert_eq!(total, Total(7));
+        assert_eq!(actor.recv_timeout(Some(0)).unwrap().popstr().unwrap().unwrap(), "event");
+        assert_eq!(actor.recv_timeout(Some(0)).unwrap_err().kind(), ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_spawn_panic() {
+        ZSys::init();
+
+        let actor = ZActor::spawn(|_| panic!("I'm not supposed to be here today")).unwrap();
+        drop(actor);
+    }
 }
@@ -0,0 +1,1434 @@
+//! Module: czmq-zap
+//!
+//! A ZAP (RFC 27) handler that hands each authentication request to a
+//! Rust callback, for when ZAuth's fixed ALLOW/DENY/PLAIN/CURVE
+//! policies aren't enough, e.g. to check CURVE keys against your own
+//! database.
+//!
+//! libzmq sends ZAP requests to whichever socket is bound at
+//! inproc://zeromq.zap.01, so only one ZapHandler or ZAuth can run
+//! per context.
+//!
+//! A ZapHandler can also log every decision it makes as a `ZapEvent`,
+//! to a callback or a PUB socket. ZAuth makes its decisions inside
+//! CZMQ, where the only record is its VERBOSE output on stderr, so run
+//! a ZapHandler instead if you need an audit log. `ZAuthPolicy` makes
+//! ZAuth's decisions, apart from the GSSAPI difference noted on it.
+
+use {Error, ErrorKind, Result, ZActor, ZCert, ZFrame, ZMsg, ZPoller, ZSock};
+use std::{error, fmt, fs};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zmq;
+
+const ZAP_ENDPOINT: &'static str = "inproc://zeromq.zap.01";
+const ZAP_VERSION: &'static str = "1.0";
+const ZAP_EVENT_TOPIC: &'static str = "ZAP";
+
+// Only one test at a time can bind the ZAP endpoint, including those
+// in the zauth module.
+#[cfg(test)]
+pub static ZAP_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ZapMechanism {
+    Null,
+    Plain { username: String, password: String },
+    Curve { public_key: Vec<u8> },
+    Gssapi { principal: Vec<u8> },
+}
+
+impl ZapMechanism {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ZapMechanism::Null => "NULL",
+            ZapMechanism::Plain { .. } => "PLAIN",
+            ZapMechanism::Curve { .. } => "CURVE",
+            ZapMechanism::Gssapi { .. } => "GSSAPI",
+        }
+    }
+}
+
+/// An authentication request from libzmq.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZapRequest {
+    pub domain: String,
+    pub address: String,
+    pub identity: Vec<u8>,
+    pub mechanism: ZapMechanism,
+    request_id: Vec<u8>,
+}
+
+impl ZapRequest {
+    /// The client's CURVE public key in Z85, as used by certificate
+    /// files and `ZCert::public_txt()`.
+    pub fn curve_public_txt(&self) -> Option<String> {
+        match self.mechanism {
+            ZapMechanism::Curve { ref public_key } => zmq::z85_encode(public_key).ok(),
+            _ => None,
+        }
+    }
+
+    fn decode(msg: &ZMsg) -> Result<ZapRequest> {
+        let version = try!(pop_str(msg));
+        if version != ZAP_VERSION {
+            return Err(Error::new(ErrorKind::InvalidArg, ZapError::BadRequest));
+        }
+
+        let request_id = try!(pop_bytes(msg));
+        let domain = try!(pop_str(msg));
+        let address = try!(pop_str(msg));
+        let identity = try!(pop_bytes(msg));
+
+        let mechanism = match &*try!(pop_str(msg)) {
+            "NULL" => ZapMechanism::Null,
+            "PLAIN" => ZapMechanism::Plain {
+                username: try!(pop_str(msg)),
+                password: try!(pop_str(msg)),
+            },
+            "CURVE" => ZapMechanism::Curve { public_key: try!(pop_bytes(msg)) },
+            "GSSAPI" => ZapMechanism::Gssapi { principal: try!(pop_bytes(msg)) },
+            _ => return Err(Error::new(ErrorKind::InvalidArg, ZapError::BadRequest)),
+        };
+
+        Ok(ZapRequest {
+            domain: domain,
+            address: address,
+            identity: identity,
+            mechanism: mechanism,
+            request_id: request_id,
+        })
+    }
+}
+
+/// A ZAP handler's decision on a `ZapRequest`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZapReply {
+    status_code: u16,
+    status_text: String,
+    user_id: String,
+    metadata: Vec<(String, Vec<u8>)>,
+}
+
+impl ZapReply {
+    /// Let the client connect. The user id is available to the server
+    /// as the "User-Id" property of each frame it receives.
+    pub fn allow(user_id: &str) -> ZapReply {
+        ZapReply::new(200, "OK", user_id)
+    }
+
+    pub fn deny(reason: &str) -> ZapReply {
+        ZapReply::new(400, reason, "")
+    }
+
+    /// Refuse the client because the handler itself failed, e.g. it
+    /// couldn't reach its user database.
+    pub fn error(reason: &str) -> ZapReply {
+        ZapReply::new(500, reason, "")
+    }
+
+    /// Attach a property that the server can read from each frame with
+    /// `ZFrame::meta()`. Names are limited to 255 bytes.
+    pub fn with_metadata(mut self, name: &str, value: &[u8]) -> ZapReply {
+        self.metadata.push((name.to_owned(), value.to_vec()));
+        self
+    }
+
+    pub fn is_allowed(&self) -> bool {
+        self.status_code == 200
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    pub fn status_text(&self) -> &str {
+        &self.status_text
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    fn new(status_code: u16, status_text: &str, user_id: &str) -> ZapReply {
+        ZapReply {
+            status_code: status_code,
+            status_text: status_text.to_owned(),
+            user_id: user_id.to_owned(),
+            metadata: Vec::new(),
+        }
+    }
+
+    fn encode(&self, request_id: &[u8]) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+        try!(msg.addstr(ZAP_VERSION));
+        try!(msg.append(try!(ZFrame::new(request_id))));
+        try!(msg.addstr(&self.status_code.to_string()));
+        try!(msg.addstr(&self.status_text));
+        try!(msg.addstr(&self.user_id));
+
+        // Metadata uses the ZMTP property encoding: a 1 byte name
+        // length, the name, a 4 byte big-endian value length, then
+        // the value.
+        let mut metadata = Vec::new();
+        for &(ref name, ref value) in &self.metadata {
+            if name.len() > 255 {
+                return Err(Error::new(ErrorKind::InvalidArg, ZapError::MetadataName));
+            }
+
+            metadata.push(name.len() as u8);
+            metadata.extend_from_slice(name.as_bytes());
+            metadata.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            metadata.extend_from_slice(value);
+        }
+        try!(msg.append(try!(ZFrame::from_vec(metadata))));
+
+        Ok(msg)
+    }
+}
+
+/// A record of one authentication decision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZapEvent {
+    pub timestamp: SystemTime,
+    pub domain: String,
+    pub address: String,
+    pub mechanism: String,
+    /// The PLAIN username, CURVE public key in Z85 or GSSAPI
+    /// principal. Passwords are never logged.
+    pub principal: Option<String>,
+    pub user_id: String,
+    pub allowed: bool,
+    pub status_code: u16,
+    pub reason: String,
+}
+
+impl ZapEvent {
+    fn new(request: &ZapRequest, reply: &ZapReply) -> ZapEvent {
+        let principal = match request.mechanism {
+            ZapMechanism::Null => None,
+            ZapMechanism::Plain { ref username, .. } => Some(username.clone()),
+            ZapMechanism::Curve { .. } => request.curve_public_txt(),
+            ZapMechanism::Gssapi { ref principal } => Some(String::from_utf8_lossy(principal).into_owned()),
+        };
+
+        ZapEvent {
+            timestamp: SystemTime::now(),
+            domain: request.domain.clone(),
+            address: request.address.clone(),
+            mechanism: request.mechanism.name().to_owned(),
+            principal: principal,
+            user_id: reply.user_id.clone(),
+            allowed: reply.is_allowed(),
+            status_code: reply.status_code,
+            reason: reply.status_text.clone(),
+        }
+    }
+
+    // For requests that couldn't be decoded. Whichever of the domain,
+    // address and mechanism frames arrived are recorded, so the log
+    // still shows where the request came from.
+    fn malformed(msg: &ZMsg, reply: &ZapReply) -> ZapEvent {
+        let mut fields = Vec::new();
+        let mut frame = msg.first();
+        while let Some(f) = frame {
+            fields.push(String::from_utf8_lossy(f.as_bytes()).into_owned());
+            frame = msg.next();
+        }
+        let field = |i: usize| fields.get(i).cloned().unwrap_or_default();
+
+        ZapEvent {
+            timestamp: SystemTime::now(),
+            domain: field(2),
+            address: field(3),
+            mechanism: field(5),
+            principal: None,
+            user_id: String::new(),
+            allowed: false,
+            status_code: reply.status_code,
+            reason: reply.status_text.clone(),
+        }
+    }
+
+    /// Encode the event as published by `ZapLog::Publish`: a "ZAP"
+    /// topic frame, then the timestamp in microseconds since the Unix
+    /// epoch, domain, address, mechanism, principal (empty if none),
+    /// user id, "ALLOW" or "DENY", status code and reason.
+    pub fn encode(&self) -> Result<ZMsg> {
+        let micros = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let micros = micros.as_secs() * 1_000_000 + micros.subsec_micros() as u64;
+
+        let msg = ZMsg::new();
+        try!(msg.addstr(ZAP_EVENT_TOPIC));
+        try!(msg.addstr(&micros.to_string()));
+        try!(msg.addstr(&self.domain));
+        try!(msg.addstr(&self.address));
+        try!(msg.addstr(&self.mechanism));
+        try!(msg.addstr(self.principal.as_ref().map_or("", |p| p)));
+        try!(msg.addstr(&self.user_id));
+        try!(msg.addstr(if self.allowed { "ALLOW" } else { "DENY" }));
+        try!(msg.addstr(&self.status_code.to_string()));
+        try!(msg.addstr(&self.reason));
+        Ok(msg)
+    }
+
+    /// Decode an event received from a `ZapLog::Publish` socket.
+    pub fn decode(msg: &ZMsg) -> Result<ZapEvent> {
+        if try!(pop_str(msg)) != ZAP_EVENT_TOPIC {
+            return Err(Error::new(ErrorKind::InvalidArg, ZapError::BadEvent));
+        }
+
+        let micros = try!(pop_number(msg));
+        let domain = try!(pop_str(msg));
+        let address = try!(pop_str(msg));
+        let mechanism = try!(pop_str(msg));
+        let principal = try!(pop_str(msg));
+        let user_id = try!(pop_str(msg));
+        let allowed = match &*try!(pop_str(msg)) {
+            "ALLOW" => true,
+            "DENY" => false,
+            _ => return Err(Error::new(ErrorKind::InvalidArg, ZapError::BadEvent)),
+        };
+        let status_code = try!(pop_number(msg)) as u16;
+        let reason = try!(pop_str(msg));
+
+        Ok(ZapEvent {
+            timestamp: UNIX_EPOCH + Duration::from_micros(micros),
+            domain: domain,
+            address: address,
+            mechanism: mechanism,
+            principal: if principal.is_empty() { None } else { Some(principal) },
+            user_id: user_id,
+            allowed: allowed,
+            status_code: status_code,
+            reason: reason,
+        })
+    }
+}
+
+/// Where a `ZapHandler` sends its `ZapEvent`s.
+pub enum ZapLog {
+    /// Called on the handler's thread, so it should be quick.
+    Callback(Box<FnMut(&ZapEvent) + Send>),
+    /// Publish each event with `ZapEvent::encode()` from a PUB socket
+    /// attached to these endpoints. As with `ZSock::new_pub()`, the
+    /// endpoints bind unless prefixed with '>'.
+    Publish(String),
+}
+
+// The actor's end of a ZapLog
+enum ZapLogSink {
+    Callback(Box<FnMut(&ZapEvent) + Send>),
+    Publish(ZSock),
+}
+
+impl ZapLogSink {
+    fn new(log: ZapLog) -> Result<ZapLogSink> {
+        match log {
+            ZapLog::Callback(f) => Ok(ZapLogSink::Callback(f)),
+            ZapLog::Publish(endpoints) => Ok(ZapLogSink::Publish(try!(ZSock::new_pub(&endpoints)))),
+        }
+    }
+
+    // Logging must never stop us answering libzmq, so failures here
+    // are dropped.
+    fn log(&mut self, event: &ZapEvent) {
+        match *self {
+            ZapLogSink::Callback(ref mut f) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| f(event)));
+            },
+            ZapLogSink::Publish(ref mut sock) => {
+                if let Ok(msg) = event.encode() {
+                    let _ = msg.send(sock);
+                }
+            },
+        }
+    }
+}
+
+/// Runs a ZAP policy callback on an actor thread until dropped.
+pub struct ZapHandler {
+    // Dropping the actor stops the handler
+    _zactor: ZActor,
+}
+
+impl ZapHandler {
+    /// Start answering ZAP requests with `policy`. Fails if another
+    /// ZapHandler or a ZAuth is already running. If `policy` panics,
+    /// the client is refused with a 500 status.
+    pub fn new<F>(policy: F) -> Result<ZapHandler>
+        where F: FnMut(&ZapRequest) -> ZapReply + Send + 'static
+    {
+        ZapHandler::spawn(policy, None)
+    }
+
+    /// As `new()`, but also log every decision to `log`.
+    pub fn with_log<F>(policy: F, log: ZapLog) -> Result<ZapHandler>
+        where F: FnMut(&ZapRequest) -> ZapReply + Send + 'static
+    {
+        ZapHandler::spawn(policy, Some(log))
+    }
+
+    fn spawn<F>(policy: F, log: Option<ZapLog>) -> Result<ZapHandler>
+        where F: FnMut(&ZapRequest) -> ZapReply + Send + 'static
+    {
+        let mut policy = policy;
+
+        let zactor = try!(ZActor::spawn(move |mut pipe| {
+            // Sockets must be created on the thread that uses them
+            let setup = ZSock::new_rep(&format!("@{}", ZAP_ENDPOINT))
+                .and_then(|zap| match log {
+                    Some(log) => ZapLogSink::new(log).map(|sink| (zap, Some(sink))),
+                    None => Ok((zap, None)),
+                });
+
+            let (mut zap, mut sink) = match setup {
+                Ok(s) => s,
+                Err(e) => {
+                    let _ = pipe.send_str(&format!("$ERROR {}", e));
+                    return;
+                },
+            };
+
+            if pipe.send_str("OK").is_err() {
+                return;
+            }
+
+            run(&mut pipe, &mut zap, &mut policy, &mut sink);
+        }));
+
+        let reply = try!(zactor.recv());
+        match reply.popstr() {
+            Some(Ok(ref s)) if s == "OK" => Ok(ZapHandler { _zactor: zactor }),
+            Some(Ok(s)) => Err(Error::new(ErrorKind::NonZero, ZapError::Bind(s.trim_start_matches("$ERROR ").to_owned()))),
+            _ => Err(Error::new(ErrorKind::NonZero, ZapError::Instantiate)),
+        }
+    }
+
+    /// Authenticate PLAIN clients against `users` and deny everyone
+    /// else, with no address lists or CURVE. See
+    /// `ZPlainUsers::authenticate()`, or `ZAuthPolicy::set_plain()` to
+    /// keep ZAuth's other checks.
+    pub fn new_plain(users: ZPlainUsers) -> Result<ZapHandler> {
+        ZapHandler::new(move |req| users.authenticate(req))
+    }
+
+    /// Authenticate clients as ZAuth would. See `ZAuthPolicy`.
+    pub fn new_zauth(policy: ZAuthPolicy) -> Result<ZapHandler> {
+        ZapHandler::new(move |req| policy.authenticate(req))
+    }
+}
+
+type PasswordVerifier = Fn(&str, &str) -> bool + Send + Sync;
+
+/// PLAIN users kept in memory rather than in a zauth password file.
+/// Clones share the same users, so a clone can be moved into a
+/// `ZapHandler` policy and updated at runtime from elsewhere; changes
+/// apply to the next client to connect.
+///
+/// By default passwords are stored and compared as plaintext. To store
+/// hashes instead, supply a verifier with `with_verifier()`.
+///
+/// On its own, `authenticate()` only checks PLAIN credentials. Pass the
+/// users to `ZAuthPolicy::set_plain()` to check them alongside address
+/// lists and CURVE certificates.
+#[derive(Clone)]
+pub struct ZPlainUsers {
+    users: Arc<RwLock<HashMap<String, String>>>,
+    verifier: Arc<PasswordVerifier>,
+}
+
+impl ZPlainUsers {
+    pub fn new() -> ZPlainUsers {
+        ZPlainUsers::with_verifier(constant_time_eq)
+    }
+
+    /// Check passwords with `verifier`, which is given the password
+    /// the client sent and the one stored for the user, e.g. a bcrypt
+    /// or argon2 hash.
+    pub fn with_verifier<F>(verifier: F) -> ZPlainUsers
+        where F: Fn(&str, &str) -> bool + Send + Sync + 'static
+    {
+        ZPlainUsers {
+            users: Arc::new(RwLock::new(HashMap::new())),
+            verifier: Arc::new(verifier),
+        }
+    }
+
+    pub fn from_map(users: HashMap<String, String>) -> ZPlainUsers {
+        let plain = ZPlainUsers::new();
+        *plain.write() = users;
+        plain
+    }
+
+    /// Add a user or replace their password.
+    pub fn insert(&self, username: &str, password: &str) {
+        self.write().insert(username.to_owned(), password.to_owned());
+    }
+
+    pub fn remove(&self, username: &str) -> bool {
+        self.write().remove(username).is_some()
+    }
+
+    pub fn clear(&self) {
+        self.write().clear();
+    }
+
+    pub fn contains(&self, username: &str) -> bool {
+        self.read().contains_key(username)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    pub fn verify(&self, username: &str, password: &str) -> bool {
+        match self.read().get(username) {
+            Some(stored) => (self.verifier)(password, stored),
+            None => false,
+        }
+    }
+
+    /// A `ZapHandler` policy that allows PLAIN clients with valid
+    /// credentials, using their username as the user id, and denies
+    /// everyone else.
+    pub fn authenticate(&self, request: &ZapRequest) -> ZapReply {
+        match request.mechanism {
+            ZapMechanism::Plain { ref username, ref password } => {
+                if self.verify(username, password) {
+                    ZapReply::allow(username)
+                } else {
+                    ZapReply::deny("Invalid username or password")
+                }
+            },
+            ref m => ZapReply::deny(&format!("{} is not supported", m.name())),
+        }
+    }
+
+    // A panicking verifier poisons the lock, but can't have left the
+    // map half-updated.
+    fn read<'a>(&'a self) -> RwLockReadGuard<'a, HashMap<String, String>> {
+        self.users.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write<'a>(&'a self) -> RwLockWriteGuard<'a, HashMap<String, String>> {
+        self.users.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Compare every byte, so that the time taken doesn't leak how much of
+// the password was right.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A `ZapHandler` policy that makes ZAuth's decisions:
+///
+/// 1. If any addresses are allowed, clients from every other address
+///    are denied. Otherwise clients from denied addresses are.
+/// 2. NULL clients that get past the address check are allowed.
+/// 3. PLAIN clients must match a user in the password file or
+///    `ZPlainUsers` given to `set_plain()`, and CURVE clients a
+///    certificate in the certificate directory (or any key after
+///    `load_curve(None)`).
+/// 4. GSSAPI clients are denied until `load_gssapi()` is called. This
+///    is the one difference: zauth allows every GSSAPI client whether
+///    or not it was sent the GSSAPI command, so call `load_gssapi()`
+///    to keep that behaviour.
+///
+/// Allowed clients get their PLAIN username, CURVE public key in Z85
+/// or GSSAPI principal as their user id, and CURVE clients also get
+/// their certificate's metadata. As with zauth, the password file and
+/// certificate directory are loaded again when they change.
+///
+/// Unlike ZAuth, addresses can be removed from the lists again, and
+/// the handler can log each decision with `ZapHandler::with_log()`.
+/// Clones share the same settings, so a clone can be moved into the
+/// handler and changed from elsewhere; changes apply to the next
+/// client to connect.
+#[derive(Clone)]
+pub struct ZAuthPolicy {
+    state: Arc<Mutex<ZAuthPolicyState>>,
+}
+
+#[derive(Default)]
+struct ZAuthPolicyState {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    plain: Option<ZAuthPlain>,
+    curve: Option<ZAuthCurve>,
+    gssapi: bool,
+}
+
+struct ZAuthPlain {
+    users: ZPlainUsers,
+    file: Option<Watched>,
+}
+
+enum ZAuthCurve {
+    Any,
+    // Each certificate's metadata, by public key in Z85
+    Certs(Watched, HashMap<String, Vec<(String, Vec<u8>)>>),
+}
+
+impl ZAuthPolicy {
+    pub fn new() -> ZAuthPolicy {
+        ZAuthPolicy {
+            state: Arc::new(Mutex::new(ZAuthPolicyState::default())),
+        }
+    }
+
+    /// Allow clients from `address`, an IPv4 or IPv6 address. Once any
+    /// address is allowed, all others are denied.
+    pub fn allow(&self, address: &str) -> Result<()> {
+        try!(check_address(address));
+
+        let mut state = self.lock();
+        if !state.allowed.iter().any(|a| a == address) {
+            state.allowed.push(address.to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn deny(&self, address: &str) -> Result<()> {
+        try!(check_address(address));
+
+        let mut state = self.lock();
+        if !state.denied.iter().any(|a| a == address) {
+            state.denied.push(address.to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn allowed(&self) -> Vec<String> {
+        self.lock().allowed.clone()
+    }
+
+    pub fn denied(&self) -> Vec<String> {
+        self.lock().denied.clone()
+    }
+
+    pub fn clear_allowed(&self) {
+        self.lock().allowed.clear();
+    }
+
+    pub fn clear_denied(&self) {
+        self.lock().denied.clear();
+    }
+
+    /// Check PLAIN clients against `users` in place of a password file.
+    /// Changes to `users` apply to the next client to connect.
+    pub fn set_plain(&self, users: ZPlainUsers) {
+        self.lock().plain = Some(ZAuthPlain {
+            users: users,
+            file: None,
+        });
+    }
+
+    /// Load PLAIN users from a zpasswd-style file of "user=password"
+    /// lines, in place of any loaded before.
+    pub fn load_plain(&self, filename: &str) -> Result<()> {
+        if !Path::new(filename).is_file() {
+            return Err(Error::new(ErrorKind::InvalidPath, ZapError::NotFound(filename.to_owned())));
+        }
+
+        let mut file = Watched::new(filename);
+        file.changed();
+        let users = ZPlainUsers::from_map(try!(read_passwords(&file.path)));
+
+        self.lock().plain = Some(ZAuthPlain {
+            users: users,
+            file: Some(file),
+        });
+        Ok(())
+    }
+
+    /// Allow CURVE clients whose public certificates are in the
+    /// `location` directory, or any CURVE client if `None`.
+    pub fn load_curve(&self, location: Option<&str>) -> Result<()> {
+        let curve = match location {
+            Some(l) => {
+                if !Path::new(l).is_dir() {
+                    return Err(Error::new(ErrorKind::InvalidPath, ZapError::NotFound(l.to_owned())));
+                }
+
+                let mut dir = Watched::new(l);
+                dir.changed();
+                let certs = try!(read_certs(&dir.path));
+                ZAuthCurve::Certs(dir, certs)
+            },
+            None => ZAuthCurve::Any,
+        };
+
+        self.lock().curve = Some(curve);
+        Ok(())
+    }
+
+    /// Allow any GSSAPI client that libzmq has authenticated, which
+    /// zauth does from the start. Fails if libzmq was built without
+    /// GSSAPI.
+    pub fn load_gssapi(&self) -> Result<()> {
+        if zmq::has("gssapi") != Some(true) {
+            return Err(Error::new(ErrorKind::InvalidArg, ZapError::Unsupported("GSSAPI")));
+        }
+
+        self.lock().gssapi = true;
+        Ok(())
+    }
+
+    pub fn authenticate(&self, request: &ZapRequest) -> ZapReply {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        state.refresh();
+
+        let address_ok = if state.allowed.is_empty() {
+            !state.denied.iter().any(|a| *a == request.address)
+        } else {
+            state.allowed.iter().any(|a| *a == request.address)
+        };
+
+        if !address_ok {
+            return ZapReply::deny("No access");
+        }
+
+        let reply = match request.mechanism {
+            ZapMechanism::Null => Some(ZapReply::allow("")),
+            ZapMechanism::Plain { ref username, ref password } => match state.plain {
+                Some(ref plain) if plain.users.verify(username, password) => Some(ZapReply::allow(username)),
+                _ => None,
+            },
+            ZapMechanism::Curve { .. } => {
+                let key = request.curve_public_txt().unwrap_or_default();
+
+                match state.curve {
+                    Some(ZAuthCurve::Any) => Some(ZapReply::allow(&key)),
+                    Some(ZAuthCurve::Certs(_, ref certs)) => certs.get(&key).map(|meta| {
+                        meta.iter().fold(ZapReply::allow(&key), |reply, &(ref name, ref value)| reply.with_metadata(name, value))
+                    }),
+                    None => None,
+                }
+            },
+            ZapMechanism::Gssapi { ref principal } if state.gssapi => {
+                Some(ZapReply::allow(&String::from_utf8_lossy(principal)))
+            },
+            ZapMechanism::Gssapi { .. } => None,
+        };
+
+        reply.unwrap_or_else(|| ZapReply::deny("No access"))
+    }
+
+    fn lock<'a>(&'a self) -> MutexGuard<'a, ZAuthPolicyState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ZAuthPolicyState {
+    // Files that fail to load keep their previous contents.
+    fn refresh(&mut self) {
+        if let Some(ZAuthPlain { ref users, file: Some(ref mut file) }) = self.plain {
+            if file.changed() {
+                if let Ok(map) = read_passwords(&file.path) {
+                    *users.write() = map;
+                }
+            }
+        }
+
+        if let Some(ZAuthCurve::Certs(ref mut dir, ref mut certs)) = self.curve {
+            if dir.changed() {
+                if let Ok(c) = read_certs(&dir.path) {
+                    *certs = c;
+                }
+            }
+        }
+    }
+}
+
+// A file or directory to load again whenever its modification time
+// changes.
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl Watched {
+    fn new(path: &str) -> Watched {
+        Watched {
+            path: PathBuf::from(path),
+            modified: None,
+        }
+    }
+
+    fn changed(&mut self) -> bool {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+
+        if modified == self.modified {
+            false
+        } else {
+            self.modified = modified;
+            true
+        }
+    }
+}
+
+// Parse a zpasswd file the way zhashx_load() does, skipping comments
+// and any line that isn't "name=value".
+fn read_passwords(path: &Path) -> Result<HashMap<String, String>> {
+    let mut users = HashMap::new();
+
+    for line in try!(fs::read_to_string(path)).lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        match line.find('=') {
+            Some(i) if i > 0 => {
+                users.insert(line[..i].to_owned(), line[i + 1..].to_owned());
+            },
+            _ => (),
+        }
+    }
+
+    Ok(users)
+}
+
+// Load every certificate under `dir`, as zcertstore does, skipping
+// files that aren't certificates.
+fn read_certs(dir: &Path) -> Result<HashMap<String, Vec<(String, Vec<u8>)>>> {
+    let mut certs = HashMap::new();
+    try!(read_certs_into(dir, &mut certs));
+    Ok(certs)
+}
+
+fn read_certs_into(dir: &Path, certs: &mut HashMap<String, Vec<(String, Vec<u8>)>>) -> Result<()> {
+    for entry in try!(fs::read_dir(dir)) {
+        let path = try!(entry).path();
+
+        if path.is_dir() {
+            try!(read_certs_into(&path, certs));
+        } else if let Ok(cert) = ZCert::load(&path) {
+            let meta = cert.meta_keys()
+                .filter_map(|k| cert.meta(k).map(|v| (k.to_owned(), v.map(|s| s.into_bytes()).unwrap_or_else(|b| b))))
+                .collect();
+            certs.insert(cert.public_txt().to_owned(), meta);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_address(address: &str) -> Result<()> {
+    match address.parse::<IpAddr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::new(ErrorKind::InvalidArg, ZapError::InvalidAddress(address.to_owned()))),
+    }
+}
+
+fn run<F>(pipe: &mut ZSock, zap: &mut ZSock, policy: &mut F, sink: &mut Option<ZapLogSink>)
+    where F: FnMut(&ZapRequest) -> ZapReply
+{
+    let mut poller = match ZPoller::new() {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+    poller.set_nonstop(true);
+
+    let (pipe_token, zap_token) = match (poller.add(pipe), poller.add(zap)) {
+        (Ok(p), Ok(z)) => (p, z),
+        _ => return,
+    };
+
+    loop {
+        let events = match poller.wait(None) {
+            Ok(events) => events,
+            Err(_) => return,
+        };
+
+        if poller.terminated() {
+            return;
+        }
+
+        for event in events {
+            if event.token == pipe_token {
+                let pipe = poller.get_mut::<ZSock>(pipe_token).unwrap();
+                match ZActor::recv_cmd(pipe) {
+                    Ok(Some(_)) => (),
+                    _ => return,
+                }
+            } else if event.token == zap_token {
+                let zap = poller.get_mut::<ZSock>(zap_token).unwrap();
+                if handle(zap, policy, sink).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn handle<F>(zap: &mut ZSock, policy: &mut F, sink: &mut Option<ZapLogSink>) -> Result<()>
+    where F: FnMut(&ZapRequest) -> ZapReply
+{
+    let msg = try!(ZMsg::recv(zap));
+    // Decoding consumes the message, so keep a copy to log if it fails
+    let original = if sink.is_some() { msg.dup().ok() } else { None };
+
+    // A REP socket must always reply, so answer malformed requests too
+    let reply = match ZapRequest::decode(&msg) {
+        Ok(request) => {
+            let reply = panic::catch_unwind(AssertUnwindSafe(|| policy(&request)))
+                .unwrap_or_else(|_| ZapReply::error("ZAP policy panicked"));
+
+            if let Some(ref mut sink) = *sink {
+                sink.log(&ZapEvent::new(&request, &reply));
+            }
+
+            try!(reply.encode(&request.request_id))
+        },
+        Err(e) => {
+            let reply = ZapReply::error(&e.to_string());
+
+            if let (Some(ref mut sink), Some(ref original)) = (sink.as_mut(), original) {
+                sink.log(&ZapEvent::malformed(original, &reply));
+            }
+
+            try!(reply.encode(&[]))
+        },
+    };
+
+    reply.send(zap)
+}
+
+fn pop_bytes(msg: &ZMsg) -> Result<Vec<u8>> {
+    match msg.pop() {
+        Some(frame) => Ok(frame.as_bytes().to_vec()),
+        None => Err(Error::new(ErrorKind::MissingFrame, ZapError::BadRequest)),
+    }
+}
+
+fn pop_str(msg: &ZMsg) -> Result<String> {
+    String::from_utf8(try!(pop_bytes(msg)))
+        .map_err(|_| Error::new(ErrorKind::StringConversion, ZapError::BadRequest))
+}
+
+fn pop_number(msg: &ZMsg) -> Result<u64> {
+    try!(pop_str(msg)).parse()
+        .map_err(|_| Error::new(ErrorKind::StringConversion, ZapError::BadEvent))
+}
+
+#[derive(Debug)]
+pub enum ZapError {
+    Instantiate,
+    Bind(String),
+    BadRequest,
+    BadEvent,
+    MetadataName,
+    InvalidAddress(String),
+    NotFound(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ZapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZapError::Instantiate => write!(f, "Could not instantiate new ZapHandler struct"),
+            ZapError::Bind(ref reason) => write!(f, "Could not bind {}: {}", ZAP_ENDPOINT, reason),
+            ZapError::BadRequest => write!(f, "Malformed ZAP request"),
+            ZapError::BadEvent => write!(f, "Malformed ZAP event"),
+            ZapError::MetadataName => write!(f, "ZAP metadata name exceeds 255 bytes"),
+            ZapError::InvalidAddress(ref a) => write!(f, "\"{}\" is not an IP address", a),
+            ZapError::NotFound(ref p) => write!(f, "Could not find {}", p),
+            ZapError::Unsupported(m) => write!(f, "libzmq was built without {} support", m),
+        }
+    }
+}
+
+impl error::Error for ZapError {
+    fn description(&self) -> &str {
+        match *self {
+            ZapError::Instantiate => "Could not instantiate new ZapHandler struct",
+            ZapError::Bind(_) => "Could not bind ZAP endpoint",
+            ZapError::BadRequest => "Malformed ZAP request",
+            ZapError::BadEvent => "Malformed ZAP event",
+            ZapError::MetadataName => "ZAP metadata name exceeds 255 bytes",
+            ZapError::InvalidAddress(_) => "Address is not an IP address",
+            ZapError::NotFound(_) => "Could not find file or directory",
+            ZapError::Unsupported(_) => "libzmq was built without support for mechanism",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use super::*;
+    use tempdir::TempDir;
+    use tempfile::NamedTempFile;
+    use {ZCert, ZSys, SocketType};
+
+    fn request(mechanism: &str, credentials: &[&[u8]]) -> ZMsg {
+        let msg = ZMsg::new();
+        msg.addstr("1.0").unwrap();
+        msg.addstr("1").unwrap();
+        msg.addstr("sky.net").unwrap();
+        msg.addstr("127.0.0.1").unwrap();
+        msg.addstr("").unwrap();
+        msg.addstr(mechanism).unwrap();
+        for c in credentials {
+            msg.addbytes(c).unwrap();
+        }
+        msg
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let req = ZapRequest::decode(&request("PLAIN", &[b"moo", b"cow"])).unwrap();
+        assert_eq!(req.domain, "sky.net");
+        assert_eq!(req.address, "127.0.0.1");
+        assert_eq!(req.mechanism, ZapMechanism::Plain { username: "moo".into(), password: "cow".into() });
+        assert_eq!(req.request_id, b"1");
+
+        let cert = ZCert::new().unwrap();
+        let req = ZapRequest::decode(&request("CURVE", &[cert.public_key()])).unwrap();
+        assert_eq!(req.curve_public_txt().unwrap(), cert.public_txt());
+
+        assert!(ZapRequest::decode(&request("PLAIN", &[b"moo"])).is_err());
+        assert!(ZapRequest::decode(&request("MOO", &[])).is_err());
+    }
+
+    #[test]
+    fn test_encode_reply() {
+        let msg = ZapReply::allow("moo").with_metadata("Cow", b"bell").encode(b"1").unwrap();
+        assert_eq!(msg.size(), 6);
+        assert_eq!(msg.popstr().unwrap().unwrap(), "1.0");
+        assert_eq!(msg.popstr().unwrap().unwrap(), "1");
+        assert_eq!(msg.popstr().unwrap().unwrap(), "200");
+        assert_eq!(msg.popstr().unwrap().unwrap(), "OK");
+        assert_eq!(msg.popstr().unwrap().unwrap(), "moo");
+        assert_eq!(msg.pop().unwrap().as_bytes(), b"\x03Cow\x00\x00\x00\x04bell");
+
+        let msg = ZapReply::deny("No").encode(b"1").unwrap();
+        msg.pop();
+        msg.pop();
+        assert_eq!(msg.popstr().unwrap().unwrap(), "400");
+        assert_eq!(msg.popstr().unwrap().unwrap(), "No");
+    }
+
+    #[test]
+    fn test_handler() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_c = requests.clone();
+
+        let handler = ZapHandler::new(move |req| {
+            requests_c.lock().unwrap().push(req.clone());
+
+            match req.mechanism {
+                ZapMechanism::Plain { ref username, ref password } if username == "moo" && password == "cow" => {
+                    ZapReply::allow(username).with_metadata("Sound", b"moo")
+                },
+                _ => ZapReply::deny("Not a cow"),
+            }
+        }).unwrap();
+
+        // Only one handler can be bound at a time
+        assert!(ZapHandler::new(|_| ZapReply::allow("")).is_err());
+
+        let mut server = ZSock::new(SocketType::PULL);
+        server.set_zap_domain("sky.net");
+        server.set_plain_server(true);
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+        let endpoint = format!("tcp://127.0.0.1:{}", port);
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("woof");
+        client.set_plain_password("dog");
+        client.set_linger(100);
+        client.set_sndtimeo(Some(100));
+        client.connect(&endpoint).unwrap();
+        sleep(Duration::from_millis(100));
+
+        let _ = client.send_str("test");
+        assert!(server.recv_str().is_err());
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("moo");
+        client.set_plain_password("cow");
+        client.set_linger(100);
+        client.connect(&endpoint).unwrap();
+
+        client.send_str("test").unwrap();
+        let frame = ZFrame::recv(&mut server).unwrap();
+        assert_eq!(frame.data().unwrap().unwrap(), "test");
+        assert_eq!(frame.meta("User-Id").unwrap().unwrap(), "moo");
+        assert_eq!(frame.meta("Sound").unwrap().unwrap(), "moo");
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.len() >= 2);
+        assert!(requests.iter().all(|r| r.domain == "sky.net" && r.address == "127.0.0.1"));
+
+        drop(handler);
+    }
+
+    #[test]
+    fn test_plain_users() {
+        let users = ZPlainUsers::new();
+        assert!(users.is_empty());
+        users.insert("moo", "cow");
+        assert!(users.verify("moo", "cow"));
+        assert!(!users.verify("moo", "co"));
+        assert!(!users.verify("woof", "cow"));
+
+        let clone = users.clone();
+        clone.insert("moo", "bell");
+        assert!(users.verify("moo", "bell"));
+        assert!(clone.remove("moo"));
+        assert!(!users.contains("moo"));
+
+        let mut map = HashMap::new();
+        map.insert("moo".to_owned(), "cow".to_owned());
+        assert_eq!(ZPlainUsers::from_map(map).len(), 1);
+    }
+
+    #[test]
+    fn test_plain_users_verifier() {
+        // Stand-in for a real password hash
+        let users = ZPlainUsers::with_verifier(|password, stored| {
+            password.chars().rev().collect::<String>() == stored
+        });
+        users.insert("moo", "woc");
+        assert!(users.verify("moo", "cow"));
+        assert!(!users.verify("moo", "woc"));
+
+        let req = ZapRequest::decode(&request("PLAIN", &[b"moo", b"cow"])).unwrap();
+        let reply = users.authenticate(&req);
+        assert!(reply.is_allowed());
+        assert_eq!(reply.user_id(), "moo");
+
+        let req = ZapRequest::decode(&request("NULL", &[])).unwrap();
+        assert!(!users.authenticate(&req).is_allowed());
+    }
+
+    #[test]
+    fn test_handler_plain_users() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let users = ZPlainUsers::new();
+        let _handler = ZapHandler::new_plain(users.clone()).unwrap();
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_plain_server(true);
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+        let endpoint = format!("tcp://127.0.0.1:{}", port);
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("moo");
+        client.set_plain_password("cow");
+        client.set_linger(100);
+        client.set_sndtimeo(Some(100));
+        client.connect(&endpoint).unwrap();
+        sleep(Duration::from_millis(100));
+
+        let _ = client.send_str("test");
+        assert!(server.recv_str().is_err());
+
+        // Takes effect without restarting the handler
+        users.insert("moo", "cow");
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("moo");
+        client.set_plain_password("cow");
+        client.set_linger(100);
+        client.connect(&endpoint).unwrap();
+
+        client.send_str("test").unwrap();
+        assert_eq!(server.recv_str().unwrap().unwrap(), "test");
+    }
+
+    #[test]
+    fn test_event_roundtrip() {
+        let req = ZapRequest::decode(&request("PLAIN", &[b"moo", b"cow"])).unwrap();
+        let event = ZapEvent::new(&req, &ZapReply::deny("Not a cow"));
+        assert_eq!(event.principal.as_ref().unwrap(), "moo");
+        assert!(!event.allowed);
+        assert_eq!(event.status_code, 400);
+
+        let decoded = ZapEvent::decode(&event.encode().unwrap()).unwrap();
+        assert_eq!(decoded.address, "127.0.0.1");
+        assert_eq!(decoded.mechanism, "PLAIN");
+        assert_eq!(decoded.reason, "Not a cow");
+        // Published timestamps are only accurate to the microsecond
+        assert!(event.timestamp.duration_since(decoded.timestamp).unwrap() < Duration::from_micros(1));
+
+        let req = ZapRequest::decode(&request("NULL", &[])).unwrap();
+        let event = ZapEvent::new(&req, &ZapReply::allow("anon"));
+        let decoded = ZapEvent::decode(&event.encode().unwrap()).unwrap();
+        assert_eq!(decoded.principal, None);
+        assert!(decoded.allowed);
+        assert_eq!(decoded.user_id, "anon");
+    }
+
+    #[test]
+    fn test_handler_log_callback() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let users = ZPlainUsers::new();
+        users.insert("moo", "cow");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_c = events.clone();
+
+        let _handler = ZapHandler::with_log(move |req| users.authenticate(req),
+                                            ZapLog::Callback(Box::new(move |e| events_c.lock().unwrap().push(e.clone())))).unwrap();
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_plain_server(true);
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("moo");
+        client.set_plain_password("cow");
+        client.set_linger(100);
+        client.connect(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+        client.send_str("test").unwrap();
+        assert_eq!(server.recv_str().unwrap().unwrap(), "test");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].principal.as_ref().unwrap(), "moo");
+        assert_eq!(events[0].user_id, "moo");
+        assert!(events[0].allowed);
+    }
+
+    #[test]
+    fn test_handler_log_publish() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let _handler = ZapHandler::with_log(|_| ZapReply::deny("Nobody"),
+                                            ZapLog::Publish("@inproc://zap_test_handler_log_publish".into())).unwrap();
+
+        let mut sub = ZSock::new_sub(">inproc://zap_test_handler_log_publish", Some("ZAP")).unwrap();
+        sub.set_rcvtimeo(Some(1000));
+        sleep(Duration::from_millis(100));
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_plain_server(true);
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_plain_username("woof");
+        client.set_plain_password("dog");
+        client.set_linger(0);
+        client.connect(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+
+        let event = ZapEvent::decode(&ZMsg::recv(&mut sub).unwrap()).unwrap();
+        assert_eq!(event.mechanism, "PLAIN");
+        assert_eq!(event.principal.unwrap(), "woof");
+        assert!(!event.allowed);
+        assert_eq!(event.reason, "Nobody");
+    }
+
+    #[test]
+    fn test_handler_log_malformed() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events_c = events.clone();
+        let _handler = ZapHandler::with_log(|_| ZapReply::allow(""),
+                                            ZapLog::Callback(Box::new(move |e| events_c.lock().unwrap().push(e.clone())))).unwrap();
+
+        let mut req = ZSock::new_req(ZAP_ENDPOINT).unwrap();
+        req.set_rcvtimeo(Some(1000));
+        request("MOO", &[]).send(&mut req).unwrap();
+
+        let reply = ZMsg::recv(&mut req).unwrap();
+        reply.pop();
+        reply.pop();
+        assert_eq!(reply.popstr().unwrap().unwrap(), "500");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].address, "127.0.0.1");
+        assert_eq!(events[0].mechanism, "MOO");
+        assert!(!events[0].allowed);
+        assert_eq!(events[0].status_code, 500);
+    }
+
+    #[test]
+    fn test_zauth_policy() {
+        let policy = ZAuthPolicy::new();
+        let null = ZapRequest::decode(&request("NULL", &[])).unwrap();
+        assert!(policy.authenticate(&null).is_allowed());
+
+        policy.deny("127.0.0.1").unwrap();
+        assert!(!policy.authenticate(&null).is_allowed());
+        policy.clear_denied();
+        policy.allow("::1").unwrap();
+        assert!(!policy.authenticate(&null).is_allowed());
+        policy.allow("127.0.0.1").unwrap();
+        assert!(policy.authenticate(&null).is_allowed());
+        assert_eq!(policy.allowed(), vec!["::1", "127.0.0.1"]);
+        policy.clear_allowed();
+        assert_eq!(policy.allow("localhost").unwrap_err().kind(), ErrorKind::InvalidArg);
+
+        // An allowed address doesn't excuse a bad password
+        let plain = ZapRequest::decode(&request("PLAIN", &[b"moo", b"cow"])).unwrap();
+        assert!(!policy.authenticate(&plain).is_allowed());
+
+        let mut passwd_file = NamedTempFile::new().unwrap();
+        passwd_file.write_all(b"# Cows only\nmoo=cow\n=nobody\n").unwrap();
+        policy.load_plain(passwd_file.path().to_str().unwrap()).unwrap();
+        let reply = policy.authenticate(&plain);
+        assert!(reply.is_allowed());
+        assert_eq!(reply.user_id(), "moo");
+        assert_eq!(policy.load_plain("/nonexistent/passwd").unwrap_err().kind(), ErrorKind::InvalidPath);
+
+        let client_cert = ZCert::new().unwrap();
+        client_cert.set_meta("moo", "cow");
+        let curve = ZapRequest::decode(&request("CURVE", &[client_cert.public_key()])).unwrap();
+        assert!(!policy.authenticate(&curve).is_allowed());
+
+        let dir = TempDir::new("czmq_test").unwrap();
+        policy.load_curve(dir.path().to_str()).unwrap();
+        assert!(!policy.authenticate(&curve).is_allowed());
+
+        // Picked up without loading the directory again
+        client_cert.save_public(dir.path().join("client.txt")).unwrap();
+        let reply = policy.authenticate(&curve);
+        assert!(reply.is_allowed());
+        assert_eq!(reply.user_id(), client_cert.public_txt());
+        let msg = reply.encode(b"1").unwrap();
+        assert_eq!(msg.size(), 6);
+        for _ in 0..5 {
+            msg.pop();
+        }
+        assert_eq!(msg.pop().unwrap().as_bytes(), b"\x03moo\x00\x00\x00\x03cow");
+
+        policy.load_curve(None).unwrap();
+        let stranger = ZapRequest::decode(&request("CURVE", &[ZCert::new().unwrap().public_key()])).unwrap();
+        assert!(policy.authenticate(&stranger).is_allowed());
+    }
+
+    #[test]
+    fn test_zauth_policy_plain_users() {
+        let users = ZPlainUsers::new();
+        let policy = ZAuthPolicy::new();
+        policy.set_plain(users.clone());
+        policy.load_curve(None).unwrap();
+
+        let plain = ZapRequest::decode(&request("PLAIN", &[b"moo", b"cow"])).unwrap();
+        assert!(!policy.authenticate(&plain).is_allowed());
+        users.insert("moo", "cow");
+        assert!(policy.authenticate(&plain).is_allowed());
+
+        // Unlike ZPlainUsers::authenticate(), the address lists and
+        // CURVE still apply.
+        let curve = ZapRequest::decode(&request("CURVE", &[ZCert::new().unwrap().public_key()])).unwrap();
+        assert!(policy.authenticate(&curve).is_allowed());
+        policy.deny("127.0.0.1").unwrap();
+        assert!(!policy.authenticate(&plain).is_allowed());
+        assert!(!policy.authenticate(&curve).is_allowed());
+    }
+
+    #[test]
+    fn test_handler_zauth_policy() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let policy = ZAuthPolicy::new();
+        policy.deny("127.0.0.1").unwrap();
+        let _handler = ZapHandler::new_zauth(policy.clone()).unwrap();
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_zap_domain("sky.net");
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+        let endpoint = format!("tcp://127.0.0.1:{}", port);
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_linger(100);
+        client.set_sndtimeo(Some(100));
+        client.connect(&endpoint).unwrap();
+        sleep(Duration::from_millis(100));
+
+        let _ = client.send_str("test");
+        assert!(server.recv_str().is_err());
+
+        // Which zauth can't do
+        policy.clear_denied();
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_linger(100);
+        client.connect(&endpoint).unwrap();
+        client.send_str("test").unwrap();
+        assert_eq!(server.recv_str().unwrap().unwrap(), "test");
+    }
+
+    #[test]
+    fn test_handler_curve() {
+        ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let client_cert = ZCert::new().unwrap();
+        let allowed = client_cert.public_txt().to_owned();
+
+        let _handler = ZapHandler::new(move |req| {
+            if req.curve_public_txt().map_or(false, |k| k == allowed) {
+                ZapReply::allow("known-client")
+            } else {
+                panic!("Unknown client");
+            }
+        }).unwrap();
+
+        let mut server = ZSock::new(SocketType::PULL);
+        let server_cert = ZCert::new().unwrap();
+        server_cert.apply(&mut server);
+        server.set_curve_server(true);
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+        let endpoint = format!("tcp://127.0.0.1:{}", port);
+
+        // A panicking policy refuses the client, rather than killing
+        // the handler.
+        let mut stranger = ZSock::new(SocketType::PUSH);
+        ZCert::new().unwrap().apply(&mut stranger);
+        stranger.set_curve_serverkey(server_cert.public_txt());
+        stranger.set_linger(100);
+        stranger.set_sndtimeo(Some(100));
+        stranger.connect(&endpoint).unwrap();
+        sleep(Duration::from_millis(100));
+        let _ = stranger.send_str("test");
+        assert!(server.recv_str().is_err());
+
+        let mut client = ZSock::new(SocketType::PUSH);
+        client_cert.apply(&mut client);
+        client.set_curve_serverkey(server_cert.public_txt());
+        client.set_linger(100);
+        client.connect(&endpoint).unwrap();
+
+        client.send_str("test").unwrap();
+        let frame = ZFrame::recv(&mut server).unwrap();
+        assert_eq!(frame.meta("User-Id").unwrap().unwrap(), "known-client");
+    }
+}
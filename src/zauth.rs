@@ -1,13 +1,58 @@
 //! Module: czmq-zauth
 
 use {czmq_sys, RawInterface, Result, ZActor, ZCertStore, ZMsg};
+use zactor::{pop_cmd_str, ZActorError, ZActorMessage};
 use error::{Error, ErrorKind};
 use std::{error, ptr};
+use std::cell::{Cell, RefCell};
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::IpAddr;
 use std::os::raw::c_void;
+use std::path::Path;
+use zmq;
+
+// zauth acknowledges every command straight away, so a reply taking
+// this long means the actor has died.
+const COMMAND_TIMEOUT: i32 = 5000;
+
+/// How ZAuth treats clients by address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ZAuthMode {
+    /// Any address that hasn't been denied may connect.
+    AllowAll,
+    /// Only allowed addresses may connect. ZAuth switches to this mode
+    /// as soon as any address is allowed.
+    Whitelist,
+}
+
+/// The configuration ZAuth has been given so far. zauth itself can't
+/// be queried, so this is recorded as each command succeeds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ZAuthConfig {
+    pub allowed: Vec<String>,
+    pub denied: Vec<String>,
+    pub plain: Option<String>,
+    /// `Some(None)` allows any CURVE client.
+    pub curve: Option<Option<String>>,
+    pub gssapi: bool,
+    pub verbose: bool,
+}
 
+/// zauth can add to its allow and deny lists but never remove from
+/// them. Restarting it to start afresh would leave no ZAP handler bound
+/// in the meantime, and libzmq lets every client in while that's the
+/// case, so ZAuth doesn't try. If your lists change at runtime, run
+/// `ZapHandler::new_zauth()` instead, whose `ZAuthPolicy` makes the
+/// same decisions as ZAuth but can also remove addresses.
+///
+/// If zauth fails to acknowledge a command in time, its late reply
+/// would be taken as the acknowledgement of the next command, so every
+/// later command fails with `ZAuthError::Unresponsive`. Drop the ZAuth
+/// and create a new one.
 pub struct ZAuth {
     zactor: ZActor,
+    config: RefCell<ZAuthConfig>,
+    unresponsive: Cell<bool>,
 }
 
 unsafe impl Send for ZAuth {}
@@ -26,71 +71,234 @@ impl ZAuth {
         } else {
             Ok(ZAuth {
                 zactor: unsafe { ZActor::from_raw(zactor as *mut c_void, true) },
+                config: RefCell::new(ZAuthConfig::default()),
+                unresponsive: Cell::new(false),
             })
         }
     }
 
+    /// Allow clients from `address`, an IPv4 or IPv6 address. This
+    /// puts ZAuth in `ZAuthMode::Whitelist`.
     pub fn allow(&self, address: &str) -> Result<()> {
-        let msg = ZMsg::new();
-        try!(msg.addstr("ALLOW"));
-        try!(msg.addstr(address));
+        try!(check_address(address));
+        try!(self.command(ZAuthCommand::Allow(address.to_owned())));
 
-        try!(self.zactor.send(msg));
-        self.zactor.sock().wait()
+        let mut config = self.config.borrow_mut();
+        if !config.allowed.iter().any(|a| a == address) {
+            config.allowed.push(address.to_owned());
+        }
+        Ok(())
     }
 
     pub fn deny(&self, address: &str) -> Result<()> {
-        let msg = ZMsg::new();
-        try!(msg.addstr("DENY"));
-        try!(msg.addstr(address));
+        try!(check_address(address));
+        try!(self.command(ZAuthCommand::Deny(address.to_owned())));
 
-        try!(self.zactor.send(msg));
-        self.zactor.sock().wait()
+        let mut config = self.config.borrow_mut();
+        if !config.denied.iter().any(|a| a == address) {
+            config.denied.push(address.to_owned());
+        }
+        Ok(())
     }
 
+    /// Load PLAIN users from a zpasswd-style file of "user=password"
+    /// lines.
+    ///
+    /// To keep credentials in memory instead, pass `ZPlainUsers` to
+    /// `ZAuthPolicy::set_plain()` and run `ZapHandler::new_zauth()` in
+    /// place of ZAuth. Only one ZAP handler can run, so note that
+    /// `ZapHandler::new_plain()` is not a drop-in alternative: it
+    /// replaces ZAuth entirely, so allow/deny lists and CURVE clients
+    /// are no longer checked and every non-PLAIN client is denied.
     pub fn load_plain(&self, filename: &str) -> Result<()> {
-        let msg = ZMsg::new();
-        try!(msg.addstr("PLAIN"));
-        try!(msg.addstr(filename));
+        if !Path::new(filename).is_file() {
+            return Err(Error::new(ErrorKind::InvalidPath, ZAuthError::NotFound(filename.to_owned())));
+        }
 
-        try!(self.zactor.send(msg));
-        self.zactor.sock().wait()
+        try!(self.command(ZAuthCommand::Plain(filename.to_owned())));
+        self.config.borrow_mut().plain = Some(filename.to_owned());
+        Ok(())
     }
 
+    /// Allow CURVE clients whose public certificates are in the
+    /// `location` directory, or any CURVE client if `None`.
     pub fn load_curve(&self, location: Option<&str>) -> Result<()> {
-        let msg = ZMsg::new();
-        try!(msg.addstr("CURVE"));
-
-        if let Some(loc) = location {
-            try!(msg.addstr(loc));
-        } else {
-            try!(msg.addstr("*"));
+        if let Some(l) = location {
+            if !Path::new(l).is_dir() {
+                return Err(Error::new(ErrorKind::InvalidPath, ZAuthError::NotFound(l.to_owned())));
+            }
         }
 
-        try!(self.zactor.send(msg));
-        self.zactor.sock().wait()
+        try!(self.command(ZAuthCommand::Curve(location.map(|l| l.to_owned()))));
+        self.config.borrow_mut().curve = Some(location.map(|l| l.to_owned()));
+        Ok(())
     }
 
-    // XXX This is unimplemented upstream, so it's just a placeholder.
+    /// Tell zauth to allow GSSAPI clients. zauth already allows any
+    /// GSSAPI client that libzmq has authenticated, whether or not
+    /// this is called, so this only records the setting in `config()`
+    /// and checks that libzmq was built with GSSAPI, failing if not.
+    /// zauth doesn't check principals itself, so use a `ZapHandler` if
+    /// you need to.
     pub fn load_gssapi(&self) -> Result<()> {
-        unimplemented!();
+        if zmq::has("gssapi") != Some(true) {
+            return Err(Error::new(ErrorKind::InvalidArg, ZAuthError::Unsupported("GSSAPI")));
+        }
+
+        try!(self.command(ZAuthCommand::Gssapi));
+        self.config.borrow_mut().gssapi = true;
+        Ok(())
     }
 
+    /// Log zauth's decisions to stderr. These can't be intercepted, so
+    /// for a structured audit log run a `ZapHandler` with a
+    /// `ZAuthPolicy` and a `ZapLog` in place of ZAuth.
     pub fn verbose(&self) -> Result<()> {
-        try!(self.zactor.send_str("VERBOSE"));
-        self.zactor.sock().wait()
+        try!(self.command(ZAuthCommand::Verbose));
+        self.config.borrow_mut().verbose = true;
+        Ok(())
+    }
+
+    pub fn config(&self) -> ZAuthConfig {
+        self.config.borrow().clone()
+    }
+
+    pub fn allowed(&self) -> Vec<String> {
+        self.config.borrow().allowed.clone()
+    }
+
+    pub fn denied(&self) -> Vec<String> {
+        self.config.borrow().denied.clone()
+    }
+
+    pub fn mode(&self) -> ZAuthMode {
+        if self.config.borrow().allowed.is_empty() {
+            ZAuthMode::AllowAll
+        } else {
+            ZAuthMode::Whitelist
+        }
+    }
+
+    // Every zauth command is acknowledged with a signal.
+    fn command(&self, cmd: ZAuthCommand) -> Result<()> {
+        if self.unresponsive.get() {
+            return Err(Error::new(ErrorKind::NonZero, ZAuthError::Unresponsive));
+        }
+
+        try!(self.zactor.send_cmd(&cmd));
+
+        let sock = self.zactor.sock();
+        let rcvtimeo = sock.rcvtimeo();
+        sock.set_rcvtimeo(Some(COMMAND_TIMEOUT));
+        let rc = sock.wait().map_err(|_| unsafe { czmq_sys::zmq_errno() });
+        sock.set_rcvtimeo(rcvtimeo);
+
+        // An acknowledgement may still be on its way, which would put
+        // every later command out of step.
+        rc.map_err(|errno| {
+            self.unresponsive.set(true);
+
+            match zmq::Error::from_raw(errno) {
+                zmq::Error::EAGAIN => Error::new(ErrorKind::Timeout, ZAuthError::Timeout(cmd.name())),
+                e => Error::new(ErrorKind::NonZero, e),
+            }
+        })
+    }
+}
+
+fn check_address(address: &str) -> Result<()> {
+    match address.parse::<IpAddr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::new(ErrorKind::InvalidArg, ZAuthError::InvalidAddress(address.to_owned()))),
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+enum ZAuthCommand {
+    Allow(String),
+    Deny(String),
+    Plain(String),
+    Curve(Option<String>),
+    Gssapi,
+    Verbose,
+}
+
+impl ZAuthCommand {
+    fn name(&self) -> &'static str {
+        match *self {
+            ZAuthCommand::Allow(_) => "ALLOW",
+            ZAuthCommand::Deny(_) => "DENY",
+            ZAuthCommand::Plain(_) => "PLAIN",
+            ZAuthCommand::Curve(_) => "CURVE",
+            ZAuthCommand::Gssapi => "GSSAPI",
+            ZAuthCommand::Verbose => "VERBOSE",
+        }
+    }
+}
+
+impl ZActorMessage for ZAuthCommand {
+    fn encode(&self) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+
+        match *self {
+            ZAuthCommand::Allow(ref address) => {
+                try!(msg.addstr("ALLOW"));
+                try!(msg.addstr(address));
+            },
+            ZAuthCommand::Deny(ref address) => {
+                try!(msg.addstr("DENY"));
+                try!(msg.addstr(address));
+            },
+            ZAuthCommand::Plain(ref filename) => {
+                try!(msg.addstr("PLAIN"));
+                try!(msg.addstr(filename));
+            },
+            ZAuthCommand::Curve(ref location) => {
+                try!(msg.addstr("CURVE"));
+                try!(msg.addstr(location.as_ref().map_or("*", |l| l)));
+            },
+            ZAuthCommand::Gssapi => try!(msg.addstr("GSSAPI")),
+            ZAuthCommand::Verbose => try!(msg.addstr("VERBOSE")),
+        }
+
+        Ok(msg)
+    }
+
+    fn decode(msg: ZMsg) -> Result<ZAuthCommand> {
+        match try!(pop_cmd_str(&msg)).as_ref() {
+            "ALLOW" => Ok(ZAuthCommand::Allow(try!(pop_cmd_str(&msg)))),
+            "DENY" => Ok(ZAuthCommand::Deny(try!(pop_cmd_str(&msg)))),
+            "PLAIN" => Ok(ZAuthCommand::Plain(try!(pop_cmd_str(&msg)))),
+            "CURVE" => match try!(pop_cmd_str(&msg)) {
+                ref l if l == "*" => Ok(ZAuthCommand::Curve(None)),
+                l => Ok(ZAuthCommand::Curve(Some(l))),
+            },
+            "GSSAPI" => Ok(ZAuthCommand::Gssapi),
+            "VERBOSE" => Ok(ZAuthCommand::Verbose),
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum ZAuthError {
     Instantiate,
+    InvalidAddress(String),
+    NotFound(String),
+    Timeout(&'static str),
+    Unresponsive,
+    Unsupported(&'static str),
 }
 
 impl Display for ZAuthError {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         match *self {
             ZAuthError::Instantiate => write!(f, "Could not instantiate new ZAuth struct"),
+            ZAuthError::InvalidAddress(ref a) => write!(f, "\"{}\" is not an IP address", a),
+            ZAuthError::NotFound(ref p) => write!(f, "Could not find {}", p),
+            ZAuthError::Timeout(cmd) => write!(f, "ZAuth did not acknowledge {} command", cmd),
+            ZAuthError::Unresponsive => write!(f, "ZAuth stopped acknowledging commands and can't be used"),
+            ZAuthError::Unsupported(m) => write!(f, "libzmq was built without {} support", m),
         }
     }
 }
@@ -99,18 +307,25 @@ impl error::Error for ZAuthError {
     fn description(&self) -> &str {
         match *self {
             ZAuthError::Instantiate => "Could not instantiate new ZAuth struct",
+            ZAuthError::InvalidAddress(_) => "Address is not an IP address",
+            ZAuthError::NotFound(_) => "Could not find file or directory",
+            ZAuthError::Timeout(_) => "ZAuth did not acknowledge command",
+            ZAuthError::Unresponsive => "ZAuth stopped acknowledging commands",
+            ZAuthError::Unsupported(_) => "libzmq was built without support for mechanism",
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::error::Error as StdError;
     use std::io::Write;
     use std::thread::sleep;
     use std::time::Duration;
     use super::*;
     use tempdir::TempDir;
     use tempfile::NamedTempFile;
+    use zap::ZAP_TEST_LOCK;
     use {ZCert, ZFrame, ZSock, SocketType, ZSys};
     #[cfg(feature = "draft")]
     use {RawInterface, ZCertStore, ZCertStoreRaw};
@@ -121,19 +336,76 @@ mod tests {
     #[test]
     fn test_zauth() {
         ZSys::init();
+        let _lock = ZAP_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
 
         test_verbose();
+        test_config();
+        test_unresponsive();
         test_allow_deny();
         test_plain();
         test_curve();
         test_zcertstore();
     }
 
+    #[test]
+    fn test_command_roundtrip() {
+        let cmds = vec![
+            ZAuthCommand::Allow("127.0.0.1".into()),
+            ZAuthCommand::Curve(None),
+            ZAuthCommand::Curve(Some("/tmp/certs".into())),
+            ZAuthCommand::Verbose,
+        ];
+
+        for cmd in cmds {
+            assert_eq!(ZAuthCommand::decode(cmd.encode().unwrap()).unwrap(), cmd);
+        }
+    }
+
     fn test_verbose() {
         let zauth = ZAuth::new(None).unwrap();
         assert!(zauth.verbose().is_ok());
     }
 
+    fn test_config() {
+        let zauth = ZAuth::new(None).unwrap();
+        assert_eq!(zauth.mode(), ZAuthMode::AllowAll);
+
+        zauth.allow("127.0.0.1").unwrap();
+        zauth.allow("127.0.0.1").unwrap();
+        zauth.deny("::1").unwrap();
+        zauth.load_curve(None).unwrap();
+        assert_eq!(zauth.allowed(), vec!["127.0.0.1"]);
+        assert_eq!(zauth.denied(), vec!["::1"]);
+        assert_eq!(zauth.mode(), ZAuthMode::Whitelist);
+        assert_eq!(zauth.config().curve, Some(None));
+
+        let err = zauth.allow("localhost").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidArg);
+        assert_eq!(err.source().unwrap().downcast_ref::<ZAuthError>(), Some(&ZAuthError::InvalidAddress("localhost".into())));
+        assert_eq!(zauth.load_plain("/nonexistent/passwd").unwrap_err().kind(), ErrorKind::InvalidPath);
+        assert_eq!(zauth.load_curve(Some("/nonexistent/certs")).unwrap_err().kind(), ErrorKind::InvalidPath);
+
+        match zmq::has("gssapi") {
+            Some(true) => {
+                zauth.load_gssapi().unwrap();
+                assert!(zauth.config().gssapi);
+            },
+            _ => {
+                let err = zauth.load_gssapi().unwrap_err();
+                assert_eq!(err.source().unwrap().downcast_ref::<ZAuthError>(), Some(&ZAuthError::Unsupported("GSSAPI")));
+            },
+        }
+    }
+
+    fn test_unresponsive() {
+        let zauth = ZAuth::new(None).unwrap();
+        zauth.unresponsive.set(true);
+
+        let err = zauth.allow("127.0.0.1").unwrap_err();
+        assert_eq!(err.source().unwrap().downcast_ref::<ZAuthError>(), Some(&ZAuthError::Unresponsive));
+        assert!(zauth.allowed().is_empty());
+    }
+
     fn test_allow_deny() {
         let server = ZSock::new(SocketType::PULL);
         server.set_zap_domain("compuglobalhypermega.net");
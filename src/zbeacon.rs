@@ -0,0 +1,250 @@
+//! Module: czmq-zbeacon
+
+use {czmq_sys, duration_ms, Error, ErrorKind, RawInterface, Result, ZActor, ZMsg};
+use std::{error, fmt, ptr};
+use std::net::IpAddr;
+use std::os::raw::c_void;
+use std::time::Duration;
+use zactor::{pop_cmd_str, ZActorError, ZActorMessage};
+
+pub struct ZBeacon {
+    zactor: ZActor,
+}
+
+unsafe impl Send for ZBeacon {}
+
+impl ZBeacon {
+    pub fn new() -> Result<ZBeacon> {
+        let zactor = unsafe { czmq_sys::zactor_new(czmq_sys::zbeacon, ptr::null_mut()) };
+
+        if zactor == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZBeaconError::Instantiate))
+        } else {
+            Ok(ZBeacon {
+                zactor: unsafe { ZActor::from_raw(zactor as *mut c_void, true) },
+            })
+        }
+    }
+
+    /// Bind the beacon to a UDP port on the interface set by
+    /// `ZSys::set_interface()`, returning our own IP address.
+    pub fn configure(&self, port: u16) -> Result<String> {
+        try!(self.zactor.send_cmd(&ZBeaconCommand::Configure(port)));
+        let reply = try!(self.zactor.recv());
+
+        // zbeacon replies with an empty hostname if it couldn't find
+        // a usable interface.
+        match try!(pop_cmd_str(&reply)) {
+            ref h if h.is_empty() => Err(Error::new(ErrorKind::InvalidArg, ZBeaconError::Configure)),
+            h => Ok(h),
+        }
+    }
+
+    /// Broadcast `data` every `interval`. A zero interval uses the
+    /// zbeacon default of one second, and intervals longer than
+    /// `i32::MAX` milliseconds (about 24.8 days) are capped at that.
+    /// Beacons are limited to 255 bytes.
+    pub fn publish(&self, data: &[u8], interval: Duration) -> Result<()> {
+        if data.len() > 255 {
+            return Err(Error::new(ErrorKind::InvalidArg, ZBeaconError::TooLarge));
+        }
+
+        let interval = duration_ms(interval) as u32;
+        self.zactor.send_cmd(&ZBeaconCommand::Publish(data.to_vec(), interval))
+    }
+
+    pub fn silence(&self) -> Result<()> {
+        self.zactor.send_cmd(&ZBeaconCommand::Silence)
+    }
+
+    /// Receive beacons whose payload starts with `filter`. An empty
+    /// filter receives everything. Beacons matching the one we
+    /// publish are never received.
+    pub fn subscribe(&self, filter: &[u8]) -> Result<()> {
+        self.zactor.send_cmd(&ZBeaconCommand::Subscribe(filter.to_vec()))
+    }
+
+    pub fn unsubscribe(&self) -> Result<()> {
+        self.zactor.send_cmd(&ZBeaconCommand::Unsubscribe)
+    }
+
+    pub fn verbose(&self) -> Result<()> {
+        self.zactor.send_cmd(&ZBeaconCommand::Verbose)
+    }
+
+    /// Receive the next beacon as a (peer address, payload) pair,
+    /// giving up after `timeout` milliseconds.
+    pub fn recv(&self, timeout: Option<u32>) -> Result<(IpAddr, Vec<u8>)> {
+        let msg = try!(self.zactor.recv_timeout(timeout));
+
+        let peer = match try!(pop_cmd_str(&msg)).parse() {
+            Ok(addr) => addr,
+            Err(e) => return Err(Error::new(ErrorKind::InvalidArg, e)),
+        };
+
+        match try!(msg.popbytes()) {
+            Some(payload) => Ok((peer, payload)),
+            None => Err(Error::new(ErrorKind::MissingFrame, ZBeaconError::MissingPayload)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ZBeaconCommand {
+    Configure(u16),
+    Publish(Vec<u8>, u32),
+    Silence,
+    Subscribe(Vec<u8>),
+    Unsubscribe,
+    Verbose,
+}
+
+impl ZActorMessage for ZBeaconCommand {
+    fn encode(&self) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+
+        match *self {
+            ZBeaconCommand::Configure(port) => {
+                try!(msg.addstr("CONFIGURE"));
+                try!(msg.addstr(&port.to_string()));
+            },
+            ZBeaconCommand::Publish(ref data, interval) => {
+                try!(msg.addstr("PUBLISH"));
+                try!(msg.addbytes(data));
+                try!(msg.addstr(&interval.to_string()));
+            },
+            ZBeaconCommand::Silence => try!(msg.addstr("SILENCE")),
+            ZBeaconCommand::Subscribe(ref filter) => {
+                try!(msg.addstr("SUBSCRIBE"));
+                try!(msg.addbytes(filter));
+            },
+            ZBeaconCommand::Unsubscribe => try!(msg.addstr("UNSUBSCRIBE")),
+            ZBeaconCommand::Verbose => try!(msg.addstr("VERBOSE")),
+        }
+
+        Ok(msg)
+    }
+
+    fn decode(msg: ZMsg) -> Result<ZBeaconCommand> {
+        match try!(pop_cmd_str(&msg)).as_ref() {
+            "CONFIGURE" => Ok(ZBeaconCommand::Configure(try!(pop_cmd_num(&msg)))),
+            "PUBLISH" => {
+                let data = try!(pop_cmd_bytes(&msg));
+                Ok(ZBeaconCommand::Publish(data, try!(pop_cmd_num(&msg))))
+            },
+            "SILENCE" => Ok(ZBeaconCommand::Silence),
+            "SUBSCRIBE" => Ok(ZBeaconCommand::Subscribe(try!(pop_cmd_bytes(&msg)))),
+            "UNSUBSCRIBE" => Ok(ZBeaconCommand::Unsubscribe),
+            "VERBOSE" => Ok(ZBeaconCommand::Verbose),
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+        }
+    }
+}
+
+fn pop_cmd_bytes(msg: &ZMsg) -> Result<Vec<u8>> {
+    try!(msg.popbytes()).ok_or(Error::new(ErrorKind::MissingFrame, ZActorError::BadCommand))
+}
+
+fn pop_cmd_num<N: ::std::str::FromStr>(msg: &ZMsg) -> Result<N> {
+    try!(pop_cmd_str(msg)).parse().map_err(|_| Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand))
+}
+
+#[derive(Debug)]
+pub enum ZBeaconError {
+    Configure,
+    Instantiate,
+    MissingPayload,
+    TooLarge,
+}
+
+impl fmt::Display for ZBeaconError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZBeaconError::Configure => write!(f, "Could not find a usable interface for ZBeacon"),
+            ZBeaconError::Instantiate => write!(f, "Could not instantiate new ZBeacon struct"),
+            ZBeaconError::MissingPayload => write!(f, "Beacon had no payload"),
+            ZBeaconError::TooLarge => write!(f, "Beacon payload exceeds 255 bytes"),
+        }
+    }
+}
+
+impl error::Error for ZBeaconError {
+    fn description(&self) -> &str {
+        match *self {
+            ZBeaconError::Configure => "Could not find a usable interface for ZBeacon",
+            ZBeaconError::Instantiate => "Could not instantiate new ZBeacon struct",
+            ZBeaconError::MissingPayload => "Beacon had no payload",
+            ZBeaconError::TooLarge => "Beacon payload exceeds 255 bytes",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use ZSys;
+
+    #[test]
+    fn test_command_roundtrip() {
+        let cmds = vec![
+            ZBeaconCommand::Configure(5670),
+            ZBeaconCommand::Publish(vec![0, 1, 2], 100),
+            ZBeaconCommand::Subscribe(Vec::new()),
+            ZBeaconCommand::Silence,
+        ];
+
+        for cmd in cmds {
+            assert_eq!(ZBeaconCommand::decode(cmd.encode().unwrap()).unwrap(), cmd);
+        }
+    }
+
+    #[test]
+    fn test_publish_too_large() {
+        ZSys::init();
+
+        let beacon = ZBeacon::new().unwrap();
+        assert!(beacon.publish(&[0; 256], Duration::from_millis(100)).is_err());
+    }
+
+    // Puts back the process-wide interface, even if the test fails.
+    struct RestoreInterface(String);
+
+    impl Drop for RestoreInterface {
+        fn drop(&mut self) {
+            let _ = ZSys::set_interface(&self.0);
+        }
+    }
+
+    // Both ends must agree on a port up front, so it can't be chosen
+    // at random. Popular ports like 9999 may already carry other
+    // beacons on the LAN or CI host, so use an obscure one and only
+    // subscribe to our own payload.
+    const TEST_PORT: u16 = 47913;
+
+    #[test]
+    fn test_beacon() {
+        ZSys::init();
+        // Bind to every interface so that broadcasts loop back to us
+        // on hosts without a LAN.
+        let _restore = RestoreInterface(ZSys::interface());
+        ZSys::set_interface("*").unwrap();
+
+        let speaker = ZBeacon::new().unwrap();
+        speaker.configure(TEST_PORT).unwrap();
+        speaker.publish(b"czmq-test-moo", Duration::from_millis(100)).unwrap();
+
+        let listener = ZBeacon::new().unwrap();
+        listener.configure(TEST_PORT).unwrap();
+        listener.subscribe(b"czmq-test-").unwrap();
+
+        let (_, payload) = listener.recv(Some(2000)).unwrap();
+        assert_eq!(payload, b"czmq-test-moo");
+
+        // Saturates rather than overflowing
+        speaker.publish(b"czmq-test-moo", Duration::from_secs(u64::max_value())).unwrap();
+
+        speaker.silence().unwrap();
+        listener.unsubscribe().unwrap();
+    }
+}
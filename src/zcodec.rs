@@ -0,0 +1,262 @@
+//! Module: czmq-zcodec
+//!
+//! Serde support for message payloads. A `ZCodec` turns a value into
+//! the bytes of a single frame and back again. JSON, MessagePack and
+//! bincode are built in, and you can plug in any other format by
+//! implementing `ZCodec` yourself.
+//!
+//! Messages can optionally carry a header frame ahead of the payload,
+//! naming the format and type as "format:tag", e.g. "json:reading".
+//! The tag is whatever the two ends agree to call the type, so peers
+//! can be built separately or written in other languages.
+
+use {bincode, rmp_serde, serde_json, Error, ErrorKind, Result, ZFrame, ZMsg};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::{error, fmt};
+
+pub trait ZCodec {
+    /// The format name written to header frames.
+    fn name(&self) -> &'static str;
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl ZCodec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+}
+
+/// MessagePack, with structs encoded as maps so that fields can be
+/// read by name from other languages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsgPack;
+
+impl ZCodec for MsgPack {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        rmp_serde::from_slice(bytes).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl ZCodec for Bincode {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(|e| Error::new(ErrorKind::Codec, e))
+    }
+}
+
+impl ZFrame {
+    pub fn from_serialize<C: ZCodec, T: Serialize>(codec: &C, value: &T) -> Result<ZFrame> {
+        ZFrame::from_vec(try!(codec.encode(value)))
+    }
+
+    pub fn deserialize<C: ZCodec, T: DeserializeOwned>(&self, codec: &C) -> Result<T> {
+        codec.decode(self.as_bytes())
+    }
+}
+
+impl ZMsg {
+    /// Create a message with `value` as its only frame.
+    pub fn from_serialize<C: ZCodec, T: Serialize>(codec: &C, value: &T) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+        try!(msg.append(try!(ZFrame::from_serialize(codec, value))));
+        Ok(msg)
+    }
+
+    /// Create a message with a "format:tag" header frame followed by
+    /// `value`.
+    pub fn from_serialize_tagged<C: ZCodec, T: Serialize>(codec: &C, tag: &str, value: &T) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+        try!(msg.addstr(&header(codec, tag)));
+        try!(msg.append(try!(ZFrame::from_serialize(codec, value))));
+        Ok(msg)
+    }
+
+    /// Deserialize the first frame and pop it. On error the message is
+    /// left as it was.
+    pub fn deserialize<C: ZCodec, T: DeserializeOwned>(&self, codec: &C) -> Result<T> {
+        let value = match self.first() {
+            Some(frame) => try!(frame.deserialize(codec)),
+            None => return Err(Error::new(ErrorKind::MissingFrame, ZCodecError::MissingPayload)),
+        };
+
+        self.pop();
+        Ok(value)
+    }
+
+    /// Deserialize a header frame and the payload that follows it,
+    /// checking that the header names this codec and `tag`, then pop
+    /// both. On error the message is left as it was.
+    pub fn deserialize_tagged<C: ZCodec, T: DeserializeOwned>(&self, codec: &C, tag: &str) -> Result<T> {
+        let found = match self.first() {
+            Some(frame) => String::from_utf8_lossy(frame.as_bytes()).into_owned(),
+            None => return Err(Error::new(ErrorKind::MissingFrame, ZCodecError::MissingHeader)),
+        };
+
+        let expected = header(codec, tag);
+        if found != expected {
+            return Err(Error::new(ErrorKind::Codec, ZCodecError::HeaderMismatch { expected: expected, found: found }));
+        }
+
+        let value = match self.next() {
+            Some(frame) => try!(frame.deserialize(codec)),
+            None => return Err(Error::new(ErrorKind::MissingFrame, ZCodecError::MissingPayload)),
+        };
+
+        self.pop();
+        self.pop();
+        Ok(value)
+    }
+}
+
+fn header<C: ZCodec>(codec: &C, tag: &str) -> String {
+    format!("{}:{}", codec.name(), tag)
+}
+
+#[derive(Debug)]
+pub enum ZCodecError {
+    MissingHeader,
+    MissingPayload,
+    HeaderMismatch { expected: String, found: String },
+}
+
+impl fmt::Display for ZCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZCodecError::MissingHeader => write!(f, "ZMsg has no header frame"),
+            ZCodecError::MissingPayload => write!(f, "ZMsg has no payload frame"),
+            ZCodecError::HeaderMismatch { ref expected, ref found } => write!(f, "Expected header \"{}\", found \"{}\"", expected, found),
+        }
+    }
+}
+
+impl error::Error for ZCodecError {
+    fn description(&self) -> &str {
+        match *self {
+            ZCodecError::MissingHeader => "ZMsg has no header frame",
+            ZCodecError::MissingPayload => "ZMsg has no payload frame",
+            ZCodecError::HeaderMismatch { .. } => "ZMsg header doesn't match the expected format and type",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ZSock, ZSys};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        station: String,
+        celsius: f32,
+        tags: Vec<String>,
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            station: "moo".into(),
+            celsius: 21.5,
+            tags: vec!["cow".into()],
+        }
+    }
+
+    fn roundtrip<C: ZCodec>(codec: C) {
+        let frame = ZFrame::from_serialize(&codec, &reading()).unwrap();
+        assert_eq!(frame.deserialize::<C, Reading>(&codec).unwrap(), reading());
+
+        let msg = ZMsg::from_serialize(&codec, &reading()).unwrap();
+        assert_eq!(msg.size(), 1);
+        assert_eq!(msg.deserialize::<C, Reading>(&codec).unwrap(), reading());
+        assert_eq!(msg.deserialize::<C, Reading>(&codec).unwrap_err().kind(), ErrorKind::MissingFrame);
+
+        // A payload that doesn't decode stays put
+        let msg = ZMsg::new();
+        msg.addstr("\u{1}").unwrap();
+        assert!(msg.deserialize::<C, Reading>(&codec).is_err());
+        assert_eq!(msg.size(), 1);
+    }
+
+    #[test]
+    fn test_json() {
+        roundtrip(Json);
+
+        let frame = ZFrame::from_serialize(&Json, &reading()).unwrap();
+        assert_eq!(frame.as_bytes(), &br#"{"station":"moo","celsius":21.5,"tags":["cow"]}"#[..]);
+    }
+
+    #[test]
+    fn test_msgpack() {
+        roundtrip(MsgPack);
+    }
+
+    #[test]
+    fn test_bincode() {
+        roundtrip(Bincode);
+    }
+
+    #[test]
+    fn test_decode_error() {
+        let frame = ZFrame::from("not json").unwrap();
+        assert_eq!(frame.deserialize::<Json, Reading>(&Json).unwrap_err().kind(), ErrorKind::Codec);
+    }
+
+    #[test]
+    fn test_tagged() {
+        ZSys::init();
+
+        let mut server = ZSock::new_pull("@inproc://zcodec_test_tagged").unwrap();
+        let mut client = ZSock::new_push(">inproc://zcodec_test_tagged").unwrap();
+
+        ZMsg::from_serialize_tagged(&MsgPack, "reading", &reading()).unwrap().send(&mut client).unwrap();
+        let msg = ZMsg::recv(&mut server).unwrap();
+        assert_eq!(msg.size(), 2);
+        assert!(msg.first().unwrap().streq("msgpack:reading"));
+        assert_eq!(msg.deserialize_tagged::<MsgPack, Reading>(&MsgPack, "reading").unwrap(), reading());
+        assert_eq!(msg.size(), 0);
+
+        // Wrong format
+        let msg = ZMsg::from_serialize_tagged(&Json, "reading", &reading()).unwrap();
+        assert_eq!(msg.deserialize_tagged::<MsgPack, Reading>(&MsgPack, "reading").unwrap_err().kind(), ErrorKind::Codec);
+        assert_eq!(msg.size(), 2);
+
+        // Wrong tag, which leaves the message for another attempt
+        let err = msg.deserialize_tagged::<Json, String>(&Json, "name").unwrap_err();
+        assert!(err.to_string().contains("Expected header \"json:name\", found \"json:reading\""));
+        assert_eq!(msg.deserialize_tagged::<Json, Reading>(&Json, "reading").unwrap(), reading());
+
+        // Right tag but the payload doesn't match the type
+        let msg = ZMsg::from_serialize_tagged(&Json, "reading", &reading()).unwrap();
+        assert_eq!(msg.deserialize_tagged::<Json, String>(&Json, "reading").unwrap_err().kind(), ErrorKind::Codec);
+        assert_eq!(msg.size(), 2);
+    }
+}
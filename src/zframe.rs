@@ -50,6 +50,64 @@ impl ZFrame {
         })
     }
 
+    /// Create a frame from an owned buffer without copying it. The
+    /// buffer is handed to CZMQ with zframe_frommem() and dropped once
+    /// libzmq is finished with it, which may be on one of its I/O
+    /// threads after the frame has been sent.
+    ///
+    /// zframe_frommem() is part of CZMQ's draft API, so this is only
+    /// zero-copy with the "draft" feature. Without it, the buffer is
+    /// copied like `new()`.
+    #[cfg(feature = "draft")]
+    pub fn from_bytes<B>(data: B) -> Result<ZFrame>
+        where B: AsRef<[u8]> + Send + 'static
+    {
+        if data.as_ref().is_empty() {
+            return Self::empty();
+        }
+
+        // Box the buffer first so that the pointer we give CZMQ stays
+        // put, even if B stores its bytes inline.
+        let data = Box::new(data);
+        let (data_ptr, size) = {
+            let bytes = (*data).as_ref();
+            (bytes.as_ptr() as *mut c_void, bytes.len())
+        };
+        let hint = Box::into_raw(data) as *mut c_void;
+
+        let zframe = unsafe {
+            czmq_sys::zframe_frommem(data_ptr, size as czmq_sys::size_t, free_bytes::<B>, hint)
+        };
+
+        // We can't tell whether CZMQ has already called the destructor,
+        // so on failure the buffer is leaked rather than risk freeing
+        // it twice.
+        if zframe == ptr::null_mut() {
+            return Err(Error::new(ErrorKind::NullPtr, ZFrameError::Instantiate));
+        }
+
+        Ok(ZFrame {
+            zframe: zframe,
+            owned: true,
+        })
+    }
+
+    /// Create a frame from an owned buffer.
+    ///
+    /// This copies the buffer like `new()`. It's only zero-copy with
+    /// the "draft" feature, as zframe_frommem() is part of CZMQ's draft
+    /// API.
+    #[cfg(not(feature = "draft"))]
+    pub fn from_bytes<B>(data: B) -> Result<ZFrame>
+        where B: AsRef<[u8]> + Send + 'static
+    {
+        Self::new(data.as_ref())
+    }
+
+    pub fn from_vec(data: Vec<u8>) -> Result<ZFrame> {
+        Self::from_bytes(data)
+    }
+
     pub fn empty() -> Result<ZFrame> {
         let zframe = unsafe { czmq_sys::zframe_new_empty() };
 
@@ -113,6 +171,18 @@ impl ZFrame {
         unsafe { czmq_sys::zframe_size(self.zframe) as usize }
     }
 
+    /// Borrow the frame's data without copying it.
+    pub fn as_bytes(&self) -> &[u8] {
+        let data = unsafe { czmq_sys::zframe_data(self.zframe) };
+        let size = self.size();
+
+        if data == ptr::null_mut() || size == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(data, size) }
+        }
+    }
+
     pub fn data(&self) -> Result<result::Result<String, Vec<u8>>> {
         let data = unsafe { czmq_sys::zframe_data(self.zframe) };
 
@@ -198,6 +268,48 @@ impl ZFrame {
         unsafe { czmq_sys::zframe_set_more(self.zframe, if more { 1 } else { 0 }) }
     }
 
+    /// The routing id of a frame received on a SERVER socket, or 0 if
+    /// it has none.
+    #[cfg(feature = "draft")]
+    pub fn routing_id(&self) -> u32 {
+        unsafe { czmq_sys::zframe_routing_id(self.zframe) }
+    }
+
+    /// Set the routing id of the peer to send this frame to on a
+    /// SERVER socket.
+    #[cfg(feature = "draft")]
+    pub fn set_routing_id(&self, routing_id: u32) {
+        unsafe { czmq_sys::zframe_set_routing_id(self.zframe, routing_id) };
+    }
+
+    /// The group of a frame received on a DISH socket.
+    #[cfg(feature = "draft")]
+    pub fn group(&self) -> Option<String> {
+        let group = unsafe { czmq_sys::zframe_group(self.zframe) };
+
+        if group == ptr::null() {
+            return None;
+        }
+
+        match unsafe { CStr::from_ptr(group) }.to_string_lossy().into_owned() {
+            ref g if g.is_empty() => None,
+            g => Some(g),
+        }
+    }
+
+    /// Tag this frame with the group to publish it to on a RADIO
+    /// socket. Groups are limited to 15 characters.
+    #[cfg(feature = "draft")]
+    pub fn set_group(&self, group: &str) -> Result<()> {
+        let group_c = try!(CString::new(group));
+        let rc = unsafe { czmq_sys::zframe_set_group(self.zframe, group_c.as_ptr()) };
+        if rc == -1 {
+            Err(Error::new(ErrorKind::InvalidArg, ZFrameError::GroupTooLong))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn eq(&self, other: &ZFrame) -> bool {
         unsafe { czmq_sys::zframe_eq(self.zframe, other.zframe) == 1 }
     }
@@ -215,6 +327,11 @@ impl ZFrame {
     }
 }
 
+#[cfg(feature = "draft")]
+unsafe extern "C" fn free_bytes<B>(hint: *mut *mut c_void) {
+    drop(Box::from_raw(*hint as *mut B));
+}
+
 impl RawInterface<czmq_sys::zframe_t> for ZFrame {
     unsafe fn from_raw(ptr: *mut czmq_sys::zframe_t, owned: bool) -> ZFrame {
         ZFrame {
@@ -237,6 +354,8 @@ impl RawInterface<czmq_sys::zframe_t> for ZFrame {
 pub enum ZFrameError {
     Instantiate,
     CmdFailed,
+    #[cfg(feature = "draft")]
+    GroupTooLong,
 }
 
 impl fmt::Display for ZFrameError {
@@ -244,6 +363,8 @@ impl fmt::Display for ZFrameError {
         match *self {
             ZFrameError::Instantiate => write!(f, "Could not instantiate new ZFrame struct"),
             ZFrameError::CmdFailed => write!(f, "ZFrame command failed"),
+            #[cfg(feature = "draft")]
+            ZFrameError::GroupTooLong => write!(f, "ZFrame group exceeds 15 characters"),
         }
     }
 }
@@ -253,6 +374,8 @@ impl error::Error for ZFrameError {
         match *self {
             ZFrameError::Instantiate => "Could not instantiate new ZFrame struct",
             ZFrameError::CmdFailed => "ZFrame command failed",
+            #[cfg(feature = "draft")]
+            ZFrameError::GroupTooLong => "ZFrame group exceeds 15 characters",
         }
     }
 }
@@ -260,6 +383,8 @@ impl error::Error for ZFrameError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use {zmq, ZSock, ZSys};
 
     #[test]
@@ -299,6 +424,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_as_bytes() {
+        let zframe = ZFrame::new(b"moo").unwrap();
+        assert_eq!(zframe.as_bytes(), b"moo");
+
+        let zframe = ZFrame::empty().unwrap();
+        assert!(zframe.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let data = vec![7u8; 1024 * 1024];
+        #[cfg(feature = "draft")]
+        let data_ptr = data.as_ptr();
+
+        let zframe = ZFrame::from_vec(data).unwrap();
+        assert_eq!(zframe.size(), 1024 * 1024);
+        // Same buffer, no copy
+        #[cfg(feature = "draft")]
+        assert_eq!(zframe.as_bytes().as_ptr(), data_ptr);
+
+        let zframe = ZFrame::from_vec(Vec::new()).unwrap();
+        assert_eq!(zframe.size(), 0);
+    }
+
+    #[test]
+    fn test_from_bytes_freed() {
+        ZSys::init();
+
+        struct Tracked(Vec<u8>, Arc<AtomicBool>);
+
+        impl AsRef<[u8]> for Tracked {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                self.1.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let freed = Arc::new(AtomicBool::new(false));
+        let zframe = ZFrame::from_bytes(Tracked(b"moo".to_vec(), freed.clone())).unwrap();
+        assert_eq!(zframe.as_bytes(), b"moo");
+
+        let mut server = ZSock::new_pull("@inproc://zframe_test_from_bytes_freed").unwrap();
+        let mut client = ZSock::new_push(">inproc://zframe_test_from_bytes_freed").unwrap();
+        zframe.send(&mut client, None).unwrap();
+
+        let zframe = ZFrame::recv(&mut server).unwrap();
+        assert_eq!(zframe.as_bytes(), b"moo");
+        drop(zframe);
+        assert!(freed.load(Ordering::SeqCst));
+    }
+
     #[test]
     fn test_dup() {
         let zframe = ZFrame::from("moo cow").unwrap();
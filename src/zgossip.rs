@@ -0,0 +1,357 @@
+//! Module: czmq-zgossip
+
+use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZActor, ZFrame, ZMsg};
+use std::{error, fmt, ptr};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use zactor::{pop_cmd_str, ZActorError, ZActorMessage};
+
+pub struct ZGossip {
+    zactor: ZActor,
+    // zgossip logs with the name we give it, so it has to outlive
+    // the actor. Fields drop in order, so this goes after zactor.
+    _name: CString,
+}
+
+unsafe impl Send for ZGossip {}
+
+impl ZGossip {
+    pub fn new(name: &str) -> Result<ZGossip> {
+        let name_c = try!(CString::new(name));
+        let zactor = unsafe { czmq_sys::zactor_new(czmq_sys::zgossip, name_c.as_ptr() as *mut c_void) };
+
+        if zactor == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZGossipError::Instantiate))
+        } else {
+            Ok(ZGossip {
+                zactor: unsafe { ZActor::from_raw(zactor as *mut c_void, true) },
+                _name: name_c,
+            })
+        }
+    }
+
+    pub fn bind(&self, endpoint: &str) -> Result<()> {
+        self.zactor.send_cmd(&ZGossipCommand::Bind(endpoint.to_owned()))
+    }
+
+    pub fn connect(&self, endpoint: &str) -> Result<()> {
+        self.zactor.send_cmd(&ZGossipCommand::Connect(endpoint.to_owned()))
+    }
+
+    /// Publish a tuple to the cluster. It's delivered to this node and
+    /// to every peer, including those that connect later.
+    ///
+    /// The zgossip actor doesn't expire tuples, so there's no TTL
+    /// here. Use `ZGossipMsg` to set a TTL on the wire.
+    pub fn publish(&self, key: &str, value: &str) -> Result<()> {
+        self.zactor.send_cmd(&ZGossipCommand::Publish(key.to_owned(), value.to_owned()))
+    }
+
+    pub fn verbose(&self) -> Result<()> {
+        self.zactor.send_cmd(&ZGossipCommand::Verbose)
+    }
+
+    /// Receive the next event, giving up after `timeout` milliseconds.
+    pub fn recv(&self, timeout: Option<u32>) -> Result<ZGossipEvent> {
+        let msg = try!(self.zactor.recv_timeout(timeout));
+
+        match try!(pop_cmd_str(&msg)).as_ref() {
+            "DELIVER" => {
+                let key = try!(pop_cmd_str(&msg));
+                Ok(ZGossipEvent::Deliver { key: key, value: try!(pop_cmd_str(&msg)) })
+            },
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZGossipError::UnknownEvent)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ZGossipEvent {
+    Deliver { key: String, value: String },
+}
+
+#[derive(Debug, PartialEq)]
+enum ZGossipCommand {
+    Bind(String),
+    Connect(String),
+    Publish(String, String),
+    Verbose,
+}
+
+impl ZActorMessage for ZGossipCommand {
+    fn encode(&self) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+
+        match *self {
+            ZGossipCommand::Bind(ref endpoint) => {
+                try!(msg.addstr("BIND"));
+                try!(msg.addstr(endpoint));
+            },
+            ZGossipCommand::Connect(ref endpoint) => {
+                try!(msg.addstr("CONNECT"));
+                try!(msg.addstr(endpoint));
+            },
+            ZGossipCommand::Publish(ref key, ref value) => {
+                try!(msg.addstr("PUBLISH"));
+                try!(msg.addstr(key));
+                try!(msg.addstr(value));
+            },
+            ZGossipCommand::Verbose => try!(msg.addstr("VERBOSE")),
+        }
+
+        Ok(msg)
+    }
+
+    fn decode(msg: ZMsg) -> Result<ZGossipCommand> {
+        match try!(pop_cmd_str(&msg)).as_ref() {
+            "BIND" => Ok(ZGossipCommand::Bind(try!(pop_cmd_str(&msg)))),
+            "CONNECT" => Ok(ZGossipCommand::Connect(try!(pop_cmd_str(&msg)))),
+            "PUBLISH" => {
+                let key = try!(pop_cmd_str(&msg));
+                Ok(ZGossipCommand::Publish(key, try!(pop_cmd_str(&msg))))
+            },
+            "VERBOSE" => Ok(ZGossipCommand::Verbose),
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ZGossipMsgId {
+    Hello,
+    Publish,
+    Ping,
+    Pong,
+    Invalid,
+}
+
+impl ZGossipMsgId {
+    fn to_raw(&self) -> i32 {
+        match *self {
+            ZGossipMsgId::Hello => 1,
+            ZGossipMsgId::Publish => 2,
+            ZGossipMsgId::Ping => 3,
+            ZGossipMsgId::Pong => 4,
+            ZGossipMsgId::Invalid => 5,
+        }
+    }
+
+    fn from_raw(id: i32) -> ZGossipMsgId {
+        match id {
+            1 => ZGossipMsgId::Hello,
+            2 => ZGossipMsgId::Publish,
+            3 => ZGossipMsgId::Ping,
+            4 => ZGossipMsgId::Pong,
+            _ => ZGossipMsgId::Invalid,
+        }
+    }
+}
+
+/// The zgossip wire protocol codec.
+pub struct ZGossipMsg {
+    zgossip_msg: *mut czmq_sys::zgossip_msg_t,
+}
+
+unsafe impl Send for ZGossipMsg {}
+
+impl Drop for ZGossipMsg {
+    fn drop(&mut self) {
+        unsafe { czmq_sys::zgossip_msg_destroy(&mut self.zgossip_msg) };
+    }
+}
+
+impl ZGossipMsg {
+    pub fn new() -> Result<ZGossipMsg> {
+        let zgossip_msg = unsafe { czmq_sys::zgossip_msg_new() };
+
+        if zgossip_msg == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZGossipError::Instantiate))
+        } else {
+            Ok(ZGossipMsg {
+                zgossip_msg: zgossip_msg,
+            })
+        }
+    }
+
+    /// Receive and parse a message, replacing this one's fields. Fails
+    /// if the message is malformed.
+    pub fn recv<S: Sockish>(&mut self, input: &mut S) -> Result<()> {
+        let rc = unsafe { czmq_sys::zgossip_msg_recv(self.zgossip_msg, input.as_mut_ptr() as *mut czmq_sys::zsock_t) };
+
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZGossipError::Malformed))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn send<S: Sockish>(&self, output: &mut S) -> Result<()> {
+        let rc = unsafe { czmq_sys::zgossip_msg_send(self.zgossip_msg, output.as_mut_ptr() as *mut czmq_sys::zsock_t) };
+
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZGossipError::CmdFailed))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn print(&self) {
+        unsafe { czmq_sys::zgossip_msg_print(self.zgossip_msg) };
+    }
+
+    /// The peer's routing id, set when receiving on a ROUTER socket.
+    pub fn routing_id(&self) -> Option<ZFrame> {
+        let ptr = unsafe { czmq_sys::zgossip_msg_routing_id(self.zgossip_msg) };
+
+        if ptr == ptr::null_mut() {
+            None
+        } else {
+            Some(unsafe { ZFrame::from_raw(ptr, false) })
+        }
+    }
+
+    /// Address a message to a peer when sending on a ROUTER socket.
+    /// The frame is copied.
+    pub fn set_routing_id(&self, routing_id: &mut ZFrame) {
+        unsafe { czmq_sys::zgossip_msg_set_routing_id(self.zgossip_msg, routing_id.as_mut_ptr()) };
+    }
+
+    pub fn id(&self) -> ZGossipMsgId {
+        ZGossipMsgId::from_raw(unsafe { czmq_sys::zgossip_msg_id(self.zgossip_msg) })
+    }
+
+    pub fn set_id(&self, id: ZGossipMsgId) {
+        unsafe { czmq_sys::zgossip_msg_set_id(self.zgossip_msg, id.to_raw()) };
+    }
+
+    pub fn command(&self) -> String {
+        unsafe { ptr_to_string(czmq_sys::zgossip_msg_command(self.zgossip_msg)) }
+    }
+
+    pub fn key(&self) -> String {
+        unsafe { ptr_to_string(czmq_sys::zgossip_msg_key(self.zgossip_msg)) }
+    }
+
+    pub fn set_key(&self, key: &str) -> Result<()> {
+        let key_c = try!(CString::new(key));
+        unsafe { czmq_sys::zgossip_msg_set_key(self.zgossip_msg, key_c.as_ptr()) };
+        Ok(())
+    }
+
+    pub fn value(&self) -> String {
+        unsafe { ptr_to_string(czmq_sys::zgossip_msg_value(self.zgossip_msg)) }
+    }
+
+    pub fn set_value(&self, value: &str) -> Result<()> {
+        let value_c = try!(CString::new(value));
+        unsafe { czmq_sys::zgossip_msg_set_value(self.zgossip_msg, value_c.as_ptr()) };
+        Ok(())
+    }
+
+    /// Time to live in seconds.
+    pub fn ttl(&self) -> u32 {
+        unsafe { czmq_sys::zgossip_msg_ttl(self.zgossip_msg) }
+    }
+
+    pub fn set_ttl(&self, ttl: u32) {
+        unsafe { czmq_sys::zgossip_msg_set_ttl(self.zgossip_msg, ttl) };
+    }
+}
+
+unsafe fn ptr_to_string(ptr: *const c_char) -> String {
+    if ptr == ptr::null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+#[derive(Debug)]
+pub enum ZGossipError {
+    CmdFailed,
+    Instantiate,
+    Malformed,
+    UnknownEvent,
+}
+
+impl fmt::Display for ZGossipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZGossipError::CmdFailed => write!(f, "ZGossip command failed"),
+            ZGossipError::Instantiate => write!(f, "Could not instantiate new ZGossip struct"),
+            ZGossipError::Malformed => write!(f, "Received a malformed ZGossip message"),
+            ZGossipError::UnknownEvent => write!(f, "Received an unknown ZGossip event"),
+        }
+    }
+}
+
+impl error::Error for ZGossipError {
+    fn description(&self) -> &str {
+        match *self {
+            ZGossipError::CmdFailed => "ZGossip command failed",
+            ZGossipError::Instantiate => "Could not instantiate new ZGossip struct",
+            ZGossipError::Malformed => "Received a malformed ZGossip message",
+            ZGossipError::UnknownEvent => "Received an unknown ZGossip event",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {ZSock, ZSys};
+    use super::*;
+
+    #[test]
+    fn test_gossip() {
+        ZSys::init();
+
+        let base = ZGossip::new("base").unwrap();
+        base.bind("inproc://zgossip_test_gossip").unwrap();
+
+        let node = ZGossip::new("node").unwrap();
+        node.connect("inproc://zgossip_test_gossip").unwrap();
+
+        base.publish("service", "tcp://127.0.0.1:5670").unwrap();
+
+        // The publishing node gets its own tuple too
+        let expected = ZGossipEvent::Deliver { key: "service".into(), value: "tcp://127.0.0.1:5670".into() };
+        assert_eq!(base.recv(Some(1000)).unwrap(), expected);
+        assert_eq!(node.recv(Some(1000)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_msg() {
+        ZSys::init();
+
+        let mut router = ZSock::new_router("inproc://zgossip_test_msg").unwrap();
+        let mut dealer = ZSock::new_dealer("inproc://zgossip_test_msg").unwrap();
+
+        let msg = ZGossipMsg::new().unwrap();
+        msg.set_id(ZGossipMsgId::Publish);
+        msg.set_key("service").unwrap();
+        msg.set_value("tcp://127.0.0.1:5670").unwrap();
+        msg.set_ttl(60);
+        msg.send(&mut dealer).unwrap();
+
+        let mut received = ZGossipMsg::new().unwrap();
+        received.recv(&mut router).unwrap();
+        assert_eq!(received.id(), ZGossipMsgId::Publish);
+        assert_eq!(received.command(), "PUBLISH");
+        assert_eq!(received.key(), "service");
+        assert_eq!(received.value(), "tcp://127.0.0.1:5670");
+        assert_eq!(received.ttl(), 60);
+        assert!(received.routing_id().is_some());
+    }
+
+    #[test]
+    fn test_msg_malformed() {
+        ZSys::init();
+
+        let mut router = ZSock::new_router("inproc://zgossip_test_msg_malformed").unwrap();
+        let dealer = ZSock::new_dealer("inproc://zgossip_test_msg_malformed").unwrap();
+        dealer.send_str("moo").unwrap();
+
+        let mut received = ZGossipMsg::new().unwrap();
+        assert!(received.recv(&mut router).is_err());
+    }
+}
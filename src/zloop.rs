@@ -0,0 +1,399 @@
+//! Module: czmq-zloop
+
+use {czmq_sys, Error, ErrorKind, Result, Sockish};
+use std::{error, fmt, ptr};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+// A panic inside a handler can't unwind through zloop, so it is
+// parked here and resumed once zloop_start() returns.
+thread_local!(static LOOP_PANIC: RefCell<Option<Box<Any + Send>>> = RefCell::new(None));
+
+/// Returned by handlers to tell the reactor whether to keep running.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ZLoopAction {
+    Continue,
+    Stop,
+}
+
+// Handlers must be 'static because the reactor keeps a pointer to
+// them for as long as they're registered, which a leaked handle could
+// otherwise stretch beyond the lifetime of any borrowed state. Share
+// state with handlers using Rc<Cell> or Rc<RefCell>.
+struct Reader<S> {
+    sock: S,
+    handler: Box<FnMut(&mut S) -> ZLoopAction>,
+}
+
+type TimerHandler = Box<FnMut() -> ZLoopAction>;
+
+// zloop_timer_end() doesn't remove a timer while the loop can still
+// run, it only queues the id until zloop_start() gets round to it, so
+// a dropped timer can fire once more. Timer and ticket handlers are
+// therefore owned by the loop. Dropping a handle swaps its handler for
+// a no-op, freeing the closure straight away, and parks the slot in
+// `retired` until the loop itself is destroyed.
+pub struct ZLoop {
+    zloop: *mut czmq_sys::zloop_t,
+    timers: RefCell<HashMap<c_int, Box<TimerHandler>>>,
+    tickets: RefCell<HashMap<usize, Box<TimerHandler>>>,
+    retired: RefCell<Vec<Box<TimerHandler>>>,
+}
+
+impl Drop for ZLoop {
+    fn drop(&mut self) {
+        unsafe { czmq_sys::zloop_destroy(&mut self.zloop) };
+    }
+}
+
+impl ZLoop {
+    pub fn new() -> Result<ZLoop> {
+        let zloop = unsafe { czmq_sys::zloop_new() };
+
+        if zloop == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZLoopError::Instantiate))
+        } else {
+            Ok(ZLoop {
+                zloop: zloop,
+                timers: RefCell::new(HashMap::new()),
+                tickets: RefCell::new(HashMap::new()),
+                retired: RefCell::new(Vec::new()),
+            })
+        }
+    }
+
+    /// Register a socket with the reactor. The loop takes ownership of
+    /// the socket until the returned handle is dropped, at which point
+    /// the reader is unregistered.
+    pub fn reader<'a, S, F>(&'a self, sock: S, handler: F) -> Result<ZLoopReader<'a, S>>
+        where S: Sockish, F: FnMut(&mut S) -> ZLoopAction + 'static {
+        let mut reader = Box::new(Reader {
+            sock: sock,
+            handler: Box::new(handler),
+        });
+
+        let sock_ptr = reader.sock.as_mut_ptr() as *mut czmq_sys::zsock_t;
+        let arg = &mut *reader as *mut Reader<S> as *mut c_void;
+        let rc = unsafe { czmq_sys::zloop_reader(self.zloop, sock_ptr, reader_shim::<S>, arg) };
+
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZLoopError::CmdFailed))
+        } else {
+            Ok(ZLoopReader {
+                zloop: self,
+                reader: reader,
+            })
+        }
+    }
+
+    /// Register a timer that fires after `delay`, `times` times. A
+    /// `times` of 0 repeats until the handle is dropped.
+    pub fn timer<'a, F>(&'a self, delay: Duration, times: usize, handler: F) -> Result<ZLoopTimer<'a>>
+        where F: FnMut() -> ZLoopAction + 'static {
+        let mut handler: Box<TimerHandler> = Box::new(Box::new(handler));
+        let arg = &mut *handler as *mut TimerHandler as *mut c_void;
+        let timer_id = unsafe {
+            czmq_sys::zloop_timer(self.zloop, duration_ms(delay), times as u64, timer_shim, arg)
+        };
+
+        if timer_id == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZLoopError::CmdFailed))
+        } else {
+            self.timers.borrow_mut().insert(timer_id, handler);
+            Ok(ZLoopTimer {
+                zloop: self,
+                timer_id: timer_id,
+            })
+        }
+    }
+
+    /// Register a ticket, which is a cheap one-shot timer that uses
+    /// the delay set by `set_ticket_delay()`. Tickets suit timeouts
+    /// that are reset often, like per-connection heartbeats.
+    pub fn ticket<'a, F>(&'a self, handler: F) -> Result<ZLoopTicket<'a>>
+        where F: FnMut() -> ZLoopAction + 'static {
+        let mut handler: Box<TimerHandler> = Box::new(Box::new(handler));
+        let arg = &mut *handler as *mut TimerHandler as *mut c_void;
+        let ticket = unsafe { czmq_sys::zloop_ticket(self.zloop, timer_shim, arg) };
+
+        if ticket == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZLoopError::CmdFailed))
+        } else {
+            self.tickets.borrow_mut().insert(ticket as usize, handler);
+            Ok(ZLoopTicket {
+                zloop: self,
+                ticket: ticket,
+            })
+        }
+    }
+
+    /// Set the delay for all tickets. This must be set before
+    /// registering any tickets.
+    pub fn set_ticket_delay(&self, delay: Duration) {
+        unsafe { czmq_sys::zloop_set_ticket_delay(self.zloop, duration_ms(delay)) };
+    }
+
+    pub fn set_max_timers(&self, max_timers: usize) {
+        unsafe { czmq_sys::zloop_set_max_timers(self.zloop, max_timers as u64) };
+    }
+
+    pub fn set_verbose(&self, verbose: bool) {
+        unsafe { czmq_sys::zloop_set_verbose(self.zloop, if verbose { 1 } else { 0 }) };
+    }
+
+    pub fn set_nonstop(&self, nonstop: bool) {
+        unsafe { czmq_sys::zloop_set_nonstop(self.zloop, if nonstop { 1 } else { 0 }) };
+    }
+
+    /// Run the reactor until a handler returns `ZLoopAction::Stop`, in
+    /// which case this returns true, or until the process is
+    /// interrupted, in which case it returns false. A panic inside a
+    /// handler stops the reactor and is resumed here.
+    pub fn start(&self) -> bool {
+        let rc = unsafe { czmq_sys::zloop_start(self.zloop) };
+
+        if let Some(payload) = LOOP_PANIC.with(|p| p.borrow_mut().take()) {
+            panic::resume_unwind(payload);
+        }
+
+        rc == -1
+    }
+
+    fn retire(&self, handler: Option<Box<TimerHandler>>) {
+        if let Some(mut handler) = handler {
+            *handler = Box::new(|| ZLoopAction::Continue);
+            self.retired.borrow_mut().push(handler);
+        }
+    }
+}
+
+pub struct ZLoopReader<'a, S: Sockish> {
+    zloop: &'a ZLoop,
+    reader: Box<Reader<S>>,
+}
+
+impl<'a, S: Sockish> Drop for ZLoopReader<'a, S> {
+    fn drop(&mut self) {
+        let sock_ptr = self.reader.sock.as_mut_ptr() as *mut czmq_sys::zsock_t;
+        unsafe { czmq_sys::zloop_reader_end(self.zloop.zloop, sock_ptr) };
+    }
+}
+
+impl<'a, S: Sockish> ZLoopReader<'a, S> {
+    /// Keep polling the socket after its handler returns an error,
+    /// rather than removing it from the reactor.
+    pub fn set_tolerant(&mut self) {
+        let sock_ptr = self.reader.sock.as_mut_ptr() as *mut czmq_sys::zsock_t;
+        unsafe { czmq_sys::zloop_reader_set_tolerant(self.zloop.zloop, sock_ptr) };
+    }
+
+    pub fn sock(&mut self) -> &mut S {
+        &mut self.reader.sock
+    }
+}
+
+pub struct ZLoopTimer<'a> {
+    zloop: &'a ZLoop,
+    timer_id: c_int,
+}
+
+impl<'a> Drop for ZLoopTimer<'a> {
+    fn drop(&mut self) {
+        // Timer ids aren't reused, so ending a timer that has already
+        // run its course is a no-op.
+        unsafe { czmq_sys::zloop_timer_end(self.zloop.zloop, self.timer_id) };
+        let handler = self.zloop.timers.borrow_mut().remove(&self.timer_id);
+        self.zloop.retire(handler);
+    }
+}
+
+pub struct ZLoopTicket<'a> {
+    zloop: &'a ZLoop,
+    ticket: *mut c_void,
+}
+
+impl<'a> Drop for ZLoopTicket<'a> {
+    fn drop(&mut self) {
+        unsafe { czmq_sys::zloop_ticket_delete(self.zloop.zloop, self.ticket) };
+        let handler = self.zloop.tickets.borrow_mut().remove(&(self.ticket as usize));
+        self.zloop.retire(handler);
+    }
+}
+
+impl<'a> ZLoopTicket<'a> {
+    /// Restart the ticket's countdown.
+    pub fn reset(&self) {
+        unsafe { czmq_sys::zloop_ticket_reset(self.zloop.zloop, self.ticket) };
+    }
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+fn dispatch<F: FnOnce() -> ZLoopAction>(handler: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(ZLoopAction::Continue) => 0,
+        Ok(ZLoopAction::Stop) => -1,
+        Err(payload) => {
+            LOOP_PANIC.with(|p| *p.borrow_mut() = Some(payload));
+            -1
+        },
+    }
+}
+
+unsafe extern "C" fn reader_shim<S: Sockish>(_zloop: *mut czmq_sys::zloop_t, _sock: *mut czmq_sys::zsock_t, arg: *mut c_void) -> c_int {
+    let reader = &mut *(arg as *mut Reader<S>);
+    dispatch(|| (reader.handler)(&mut reader.sock))
+}
+
+unsafe extern "C" fn timer_shim(_zloop: *mut czmq_sys::zloop_t, _timer_id: c_int, arg: *mut c_void) -> c_int {
+    let handler = &mut *(arg as *mut TimerHandler);
+    dispatch(|| handler())
+}
+
+#[derive(Debug)]
+pub enum ZLoopError {
+    CmdFailed,
+    Instantiate,
+}
+
+impl fmt::Display for ZLoopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZLoopError::CmdFailed => write!(f, "ZLoop command failed"),
+            ZLoopError::Instantiate => write!(f, "Could not instantiate new ZLoop struct"),
+        }
+    }
+}
+
+impl error::Error for ZLoopError {
+    fn description(&self) -> &str {
+        match *self {
+            ZLoopError::CmdFailed => "ZLoop command failed",
+            ZLoopError::Instantiate => "Could not instantiate new ZLoop struct",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use {ZSock, ZSys};
+    use super::*;
+
+    #[test]
+    fn test_timer() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        let count = Rc::new(Cell::new(0));
+
+        let c = count.clone();
+        let _timer = zloop.timer(Duration::from_millis(10), 3, move || {
+            c.set(c.get() + 1);
+            ZLoopAction::Continue
+        }).unwrap();
+        let _stop = zloop.timer(Duration::from_millis(200), 1, || ZLoopAction::Stop).unwrap();
+
+        assert!(zloop.start());
+        assert_eq!(count.get(), 3);
+    }
+
+    #[test]
+    fn test_reader() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        let pull = ZSock::new_pull("inproc://zloop_test_reader").unwrap();
+        let push = ZSock::new_push("inproc://zloop_test_reader").unwrap();
+        push.send_str("moo").unwrap();
+
+        let received = Rc::new(RefCell::new(None));
+        let r = received.clone();
+        let mut reader = zloop.reader(pull, move |sock| {
+            *r.borrow_mut() = Some(sock.recv_str().unwrap().unwrap());
+            ZLoopAction::Stop
+        }).unwrap();
+        reader.set_tolerant();
+
+        assert!(zloop.start());
+        assert_eq!(received.borrow().as_ref().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_reader_drop() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        let pull = ZSock::new_pull("inproc://zloop_test_reader_drop").unwrap();
+        let push = ZSock::new_push("inproc://zloop_test_reader_drop").unwrap();
+        push.send_str("moo").unwrap();
+
+        let called = Rc::new(Cell::new(false));
+        let c = called.clone();
+        let reader = zloop.reader(pull, move |_| {
+            c.set(true);
+            ZLoopAction::Stop
+        }).unwrap();
+        drop(reader);
+
+        let _stop = zloop.timer(Duration::from_millis(50), 1, || ZLoopAction::Stop).unwrap();
+        zloop.start();
+        assert!(!called.get());
+    }
+
+    #[test]
+    fn test_ticket() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        zloop.set_ticket_delay(Duration::from_millis(20));
+
+        let fired = Rc::new(Cell::new(false));
+        let f = fired.clone();
+        let ticket = zloop.ticket(move || {
+            f.set(true);
+            ZLoopAction::Stop
+        }).unwrap();
+        ticket.reset();
+
+        assert!(zloop.start());
+        assert!(fired.get());
+    }
+
+    #[test]
+    fn test_timer_drop() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        let called = Rc::new(Cell::new(false));
+
+        let c = called.clone();
+        let timer = zloop.timer(Duration::from_millis(10), 1, move || {
+            c.set(true);
+            ZLoopAction::Stop
+        }).unwrap();
+        drop(timer);
+
+        let _stop = zloop.timer(Duration::from_millis(50), 1, || ZLoopAction::Stop).unwrap();
+        zloop.start();
+        assert!(!called.get());
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn test_handler_panic() {
+        ZSys::init();
+
+        let zloop = ZLoop::new().unwrap();
+        let _timer = zloop.timer(Duration::from_millis(10), 1, || panic!("boom")).unwrap();
+        zloop.start();
+    }
+}
@@ -1,11 +1,12 @@
 //! Module: czmq-zmonitor
 
 use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZActor, ZMsg};
+use zactor::{pop_cmd_str, ZActorError, ZActorMessage};
 use std::{error, ptr, result};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::os::raw::c_void;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ZMonitorEvents {
     Connected,
     ConnectDelayed,
@@ -86,13 +87,7 @@ impl ZMonitor {
     }
 
     pub fn set_attrs(&self, attrs: &[ZMonitorEvents]) -> Result<()> {
-        let msg = ZMsg::new();
-        try!(msg.addstr("LISTEN"));
-        for a in attrs {
-            try!(msg.addstr(a.to_str()));
-        }
-
-        self.zactor.send(msg)
+        self.zactor.send_cmd(&ZMonitorCommand::Listen(attrs.to_vec()))
     }
 
     pub fn get_attr(&mut self) -> Result<result::Result<ZMonitorEvents, Vec<u8>>> {
@@ -108,12 +103,53 @@ impl ZMonitor {
     }
 
     pub fn start(&self) -> Result<()> {
-        try!(self.zactor.send_str("START"));
+        try!(self.zactor.send_cmd(&ZMonitorCommand::Start));
         self.zactor.sock().wait()
     }
 
     pub fn verbose(&self) -> Result<()> {
-        self.zactor.send_str("VERBOSE")
+        self.zactor.send_cmd(&ZMonitorCommand::Verbose)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ZMonitorCommand {
+    Listen(Vec<ZMonitorEvents>),
+    Start,
+    Verbose,
+}
+
+impl ZActorMessage for ZMonitorCommand {
+    fn encode(&self) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+
+        match *self {
+            ZMonitorCommand::Listen(ref attrs) => {
+                try!(msg.addstr("LISTEN"));
+                for a in attrs {
+                    try!(msg.addstr(a.to_str()));
+                }
+            },
+            ZMonitorCommand::Start => try!(msg.addstr("START")),
+            ZMonitorCommand::Verbose => try!(msg.addstr("VERBOSE")),
+        }
+
+        Ok(msg)
+    }
+
+    fn decode(msg: ZMsg) -> Result<ZMonitorCommand> {
+        match try!(pop_cmd_str(&msg)).as_ref() {
+            "LISTEN" => {
+                let mut attrs = Vec::new();
+                while msg.size() > 0 {
+                    attrs.push(ZMonitorEvents::from_str(&try!(pop_cmd_str(&msg))));
+                }
+                Ok(ZMonitorCommand::Listen(attrs))
+            },
+            "START" => Ok(ZMonitorCommand::Start),
+            "VERBOSE" => Ok(ZMonitorCommand::Verbose),
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+        }
     }
 }
 
@@ -3,6 +3,9 @@
 use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZFrame};
 use std::{error, fmt, mem, ptr, result};
 use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
 
 #[derive(Debug, Eq)]
 pub struct ZMsg {
@@ -56,9 +59,23 @@ impl ZMsg {
         }
     }
 
-    // XXX We'll have to roll our own here as we can't imitate a C
-    // file handle without C boilerplate...which we're not doing!
-    // pub fn zmsg_load(file: *mut FILE) -> *mut zmsg_t;
+    // The C fn zmsg_load takes a FILE*, so we roll our own reader for
+    // the same format instead: each frame's size as a native-endian
+    // size_t, followed by its data, until the end of the file.
+    /// Read a message written by `write_to()` or zmsg_save(). The
+    /// format has no message boundaries, so this reads the whole
+    /// stream as one message. Use `ZMsgFileReader` for files holding
+    /// more than one.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<ZMsg> {
+        let zmsg = ZMsg::new();
+        let mut buf = [0; mem::size_of::<usize>()];
+
+        while try!(read_word(reader, &mut buf, true)) {
+            try!(zmsg.append(try!(read_frame(reader, usize::from_ne_bytes(buf) as u64))));
+        }
+
+        Ok(zmsg)
+    }
 
     pub fn encode(&self) -> Result<ZFrame> {
         let zframe = unsafe { czmq_sys::zmsg_encode(self.zmsg) };
@@ -286,8 +303,21 @@ impl ZMsg {
         }
     }
 
-    // pub fn zmsg_save(_self: *mut zmsg_t, file: *mut FILE)
-    //  -> ::std::os::raw::c_int;
+    /// Write the message in the same format as zmsg_save(), so that
+    /// it can be read back with zmsg_load(). The format holds one
+    /// message per file and, like CZMQ's, isn't portable between
+    /// platforms.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut frame = self.first();
+        while let Some(f) = frame {
+            let data = f.as_bytes();
+            try!(writer.write_all(&data.len().to_ne_bytes()));
+            try!(writer.write_all(data));
+            frame = self.next();
+        }
+
+        Ok(())
+    }
 
     pub fn dup(&self) -> Result<ZMsg> {
         let ptr = unsafe { czmq_sys::zmsg_dup(self.zmsg) };
@@ -315,6 +345,96 @@ impl ZMsg {
             Ok(signal as u8)
         }
     }
+
+    // The C fns zmsg_push and zmsg_add are deprecated aliases for
+    // zmsg_prepend and zmsg_append, which are already wrapped by
+    // prepend() and append().
+
+    /// Push an address frame followed by an empty delimiter onto the
+    /// front of the message, as ROUTER sockets expect.
+    pub fn wrap(&self, frame: ZFrame) {
+        unsafe { czmq_sys::zmsg_wrap(self.zmsg, frame.into_raw()) };
+    }
+
+    /// Pop the address frame off the front of the message, along with
+    /// the empty delimiter that follows it, if there is one.
+    pub fn unwrap(&self) -> Option<ZFrame> {
+        let ptr = unsafe { czmq_sys::zmsg_unwrap(self.zmsg) };
+
+        if ptr == ptr::null_mut() {
+            None
+        } else {
+            Some(unsafe { ZFrame::from_raw(ptr, true) })
+        }
+    }
+
+    /// Push a whole envelope, such as one returned by
+    /// `pop_envelope()`, followed by an empty delimiter onto the front
+    /// of the message.
+    pub fn push_envelope(&self, envelope: ZMsg) -> Result<()> {
+        try!(self.prepend(try!(ZFrame::empty())));
+
+        let mut frames = Vec::new();
+        while let Some(frame) = envelope.pop() {
+            frames.push(frame);
+        }
+
+        for frame in frames.into_iter().rev() {
+            try!(self.prepend(frame));
+        }
+
+        Ok(())
+    }
+
+    /// Pop every address frame up to and including the first empty
+    /// delimiter, returning the addresses in order. If the message has
+    /// no delimiter, it is left untouched.
+    pub fn pop_envelope(&self) -> Result<ZMsg> {
+        let mut frame = self.first();
+        let mut has_delimiter = false;
+
+        while let Some(f) = frame {
+            if f.size() == 0 {
+                has_delimiter = true;
+                break;
+            }
+            frame = self.next();
+        }
+
+        if !has_delimiter {
+            return Err(Error::new(ErrorKind::MissingFrame, ZMsgError::MissingDelimiter));
+        }
+
+        let envelope = ZMsg::new();
+        while let Some(frame) = self.pop() {
+            if frame.size() == 0 {
+                break;
+            }
+            try!(envelope.append(frame));
+        }
+
+        Ok(envelope)
+    }
+
+    /// Split the message into its envelope and body.
+    pub fn split(self) -> Result<(ZMsg, ZMsg)> {
+        let envelope = try!(self.pop_envelope());
+        Ok((envelope, self))
+    }
+
+    /// The routing id of a message received on a SERVER socket, or 0
+    /// if it has none.
+    #[cfg(feature = "draft")]
+    pub fn routing_id(&self) -> u32 {
+        unsafe { czmq_sys::zmsg_routing_id(self.zmsg) }
+    }
+
+    /// Set the routing id of the peer to send this message to on a
+    /// SERVER socket.
+    #[cfg(feature = "draft")]
+    pub fn set_routing_id(&self, routing_id: u32) {
+        unsafe { czmq_sys::zmsg_set_routing_id(self.zmsg, routing_id) };
+    }
 }
 
 impl RawInterface<czmq_sys::zmsg_t> for ZMsg {
@@ -335,15 +455,133 @@ impl RawInterface<czmq_sys::zmsg_t> for ZMsg {
     }
 }
 
+/// Appends messages to a file that can hold any number of them, in
+/// the format read by `ZMsgFileReader`. Each message is written as its
+/// frame count, then each frame's size followed by its data, with the
+/// count and sizes as big-endian u64s. This is not the zmsg_save()
+/// format, which can only hold one message per file.
+pub struct ZMsgFileWriter {
+    writer: BufWriter<File>,
+}
+
+impl ZMsgFileWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<ZMsgFileWriter> {
+        Ok(ZMsgFileWriter::new(try!(File::create(path))))
+    }
+
+    pub fn new(file: File) -> ZMsgFileWriter {
+        ZMsgFileWriter {
+            writer: BufWriter::new(file),
+        }
+    }
+
+    pub fn write(&mut self, msg: &ZMsg) -> Result<()> {
+        try!(self.writer.write_all(&(msg.size() as u64).to_be_bytes()));
+
+        let mut frame = msg.first();
+        while let Some(f) = frame {
+            let data = f.as_bytes();
+            try!(self.writer.write_all(&(data.len() as u64).to_be_bytes()));
+            try!(self.writer.write_all(data));
+            frame = msg.next();
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(try!(self.writer.flush()))
+    }
+}
+
+/// Iterates over every message in a file written by `ZMsgFileWriter`.
+pub struct ZMsgFileReader {
+    reader: BufReader<File>,
+}
+
+impl ZMsgFileReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ZMsgFileReader> {
+        Ok(ZMsgFileReader::new(try!(File::open(path))))
+    }
+
+    pub fn new(file: File) -> ZMsgFileReader {
+        ZMsgFileReader {
+            reader: BufReader::new(file),
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<ZMsg>> {
+        let mut buf = [0; 8];
+        if !try!(read_word(&mut self.reader, &mut buf, true)) {
+            return Ok(None);
+        }
+
+        let zmsg = ZMsg::new();
+        for _ in 0..u64::from_be_bytes(buf) {
+            try!(read_word(&mut self.reader, &mut buf, false));
+            try!(zmsg.append(try!(read_frame(&mut self.reader, u64::from_be_bytes(buf)))));
+        }
+
+        Ok(Some(zmsg))
+    }
+}
+
+impl Iterator for ZMsgFileReader {
+    type Item = Result<ZMsg>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read() {
+            Ok(Some(msg)) => Some(Ok(msg)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// Fills `buf`, returning false if the reader was already at EOF and
+// `eof_ok` is set.
+fn read_word<R: Read>(reader: &mut R, buf: &mut [u8], eof_ok: bool) -> Result<bool> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 && eof_ok => return Ok(false),
+            Ok(0) => return Err(truncated()),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(true)
+}
+
+fn read_frame<R: Read>(reader: &mut R, size: u64) -> Result<ZFrame> {
+    let mut data = Vec::new();
+    try!(reader.by_ref().take(size).read_to_end(&mut data));
+
+    if data.len() as u64 != size {
+        Err(truncated())
+    } else {
+        ZFrame::from_vec(data)
+    }
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::Io, io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated ZMsg"))
+}
+
 #[derive(Debug)]
 pub enum ZMsgError {
     CmdFailed,
+    MissingDelimiter,
 }
 
 impl fmt::Display for ZMsgError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ZMsgError::CmdFailed => write!(f, "ZMsg command failed"),
+            ZMsgError::MissingDelimiter => write!(f, "ZMsg has no envelope delimiter"),
         }
     }
 }
@@ -352,13 +590,16 @@ impl error::Error for ZMsgError {
     fn description(&self) -> &str {
         match *self {
             ZMsgError::CmdFailed => "ZMsg command failed",
+            ZMsgError::MissingDelimiter => "ZMsg has no envelope delimiter",
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
     use super::*;
+    use tempdir::TempDir;
     use {zmq, ZCert, ZFrame, ZSock, ZSys};
 
     #[test]
@@ -436,6 +677,135 @@ mod tests {
         assert_eq!(msg.pop().unwrap().data().unwrap().unwrap(), "123");
     }
 
+    #[test]
+    fn test_write_read() {
+        let msg = ZMsg::new();
+        msg.addstr("moo").unwrap();
+        msg.append(ZFrame::empty().unwrap()).unwrap();
+        msg.addstr("cow").unwrap();
+
+        let mut buf = Vec::new();
+        msg.write_to(&mut buf).unwrap();
+
+        let msg = ZMsg::read_from(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(msg.size(), 3);
+        assert_eq!(msg.popstr().unwrap().unwrap(), "moo");
+        assert_eq!(msg.pop().unwrap().size(), 0);
+        assert_eq!(msg.popstr().unwrap().unwrap(), "cow");
+    }
+
+    #[test]
+    fn test_zmsg_save_format() {
+        // Laid out as zmsg_save() writes it: a size_t length and the
+        // data for each frame, with nothing else.
+        let mut buf = Vec::new();
+        for frame in &[&b"moo"[..], &b""[..], &b"cow"[..]] {
+            buf.extend_from_slice(&frame.len().to_ne_bytes());
+            buf.extend_from_slice(frame);
+        }
+
+        let msg = ZMsg::read_from(&mut Cursor::new(&buf)).unwrap();
+        assert_eq!(msg.size(), 3);
+
+        let mut written = Vec::new();
+        msg.write_to(&mut written).unwrap();
+        assert_eq!(written, buf);
+
+        assert_eq!(msg.popstr().unwrap().unwrap(), "moo");
+        assert_eq!(msg.pop().unwrap().size(), 0);
+        assert_eq!(msg.popstr().unwrap().unwrap(), "cow");
+
+        assert_eq!(ZMsg::read_from(&mut Cursor::new(Vec::new())).unwrap().size(), 0);
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let msg = ZMsg::new();
+        msg.addstr("moo").unwrap();
+
+        let mut buf = Vec::new();
+        msg.write_to(&mut buf).unwrap();
+        buf.pop();
+
+        let err = ZMsg::read_from(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Io);
+    }
+
+    #[test]
+    fn test_file_reader_writer() {
+        let dir = TempDir::new("zmsg_test_file_reader_writer").unwrap();
+        let path = dir.path().join("capture");
+
+        let mut writer = ZMsgFileWriter::create(&path).unwrap();
+        for i in 0..3 {
+            let msg = ZMsg::new();
+            msg.addstr(&i.to_string()).unwrap();
+            writer.write(&msg).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let msgs: Vec<String> = ZMsgFileReader::open(&path).unwrap()
+            .map(|m| m.unwrap().popstr().unwrap().unwrap())
+            .collect();
+        assert_eq!(msgs, vec!["0", "1", "2"]);
+    }
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let msg = ZMsg::new();
+        msg.addstr("body").unwrap();
+        msg.wrap(ZFrame::from("address").unwrap());
+        assert_eq!(msg.size(), 3);
+
+        assert_eq!(msg.unwrap().unwrap().data().unwrap().unwrap(), "address");
+        assert_eq!(msg.size(), 1);
+        assert_eq!(msg.popstr().unwrap().unwrap(), "body");
+        assert!(msg.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_envelope() {
+        let msg = ZMsg::new();
+        msg.addstr("body").unwrap();
+
+        let envelope = ZMsg::new();
+        envelope.addstr("hop1").unwrap();
+        envelope.addstr("hop2").unwrap();
+        msg.push_envelope(envelope).unwrap();
+        assert_eq!(msg.size(), 4);
+
+        let envelope = msg.pop_envelope().unwrap();
+        assert_eq!(envelope.size(), 2);
+        assert_eq!(envelope.popstr().unwrap().unwrap(), "hop1");
+        assert_eq!(envelope.popstr().unwrap().unwrap(), "hop2");
+        assert_eq!(msg.size(), 1);
+
+        // No delimiter, so nothing is popped
+        assert!(msg.pop_envelope().is_err());
+        assert_eq!(msg.size(), 1);
+    }
+
+    #[test]
+    fn test_split() {
+        ZSys::init();
+
+        let mut router = ZSock::new_router("inproc://zmsg_test_split").unwrap();
+        router.set_rcvtimeo(Some(500));
+        let req = ZSock::new_req("inproc://zmsg_test_split").unwrap();
+        req.set_identity("moo").unwrap();
+        req.send_str("ping").unwrap();
+
+        let (envelope, body) = ZMsg::recv(&mut router).unwrap().split().unwrap();
+        assert_eq!(envelope.size(), 1);
+        assert_eq!(body.popstr().unwrap().unwrap(), "ping");
+
+        let reply = ZMsg::new();
+        reply.addstr("pong").unwrap();
+        reply.push_envelope(envelope).unwrap();
+        reply.send(&mut router).unwrap();
+        assert_eq!(req.recv_str().unwrap().unwrap(), "pong");
+    }
+
     #[test]
     fn test_push_add_popstr() {
         let msg = ZMsg::new();
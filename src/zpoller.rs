@@ -1,95 +1,303 @@
 //! Module: czmq-zpoller
 
-use {czmq_sys, Error, ErrorKind, Result, Sockish};
+use {czmq_sys, Error, ErrorKind, Result, Sockish, ZSockEvents, ZSOCK_POLLIN};
 use std::{error, fmt, ptr};
-use std::os::raw::c_int;
+use std::any::Any;
+use std::os::raw::{c_long, c_short, c_void};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use zmq;
 
-pub struct ZPoller {
-    zpoller: *mut czmq_sys::zpoller_t,
+/// Identifies a reader added to a `ZPoller`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ZPollerToken(usize);
+
+/// A reader that `ZPoller::wait()` found ready, along with the events
+/// that it is ready for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ZPollerEvent {
+    pub token: ZPollerToken,
+    pub events: ZSockEvents,
+}
+
+// Lets us keep readers of different types in one list, and get them
+// back out again as their original type.
+trait PollSource: Any {
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
 }
 
-unsafe impl Send for ZPoller {}
+impl<S: Sockish + Any> PollSource for S {
+    fn as_any(&self) -> &Any {
+        self
+    }
 
-impl Drop for ZPoller {
-    fn drop(&mut self) {
-        unsafe { czmq_sys::zpoller_destroy(&mut self.zpoller) };
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
     }
 }
 
-impl ZPoller {
-    pub fn new() -> Result<ZPoller> {
-        // zpoller_new() can take one or more readers, though Rust
-        // doesn't support variadic fns except through macros.
-        let zpoller = unsafe { czmq_sys::zpoller_new(ptr::null_mut()) };
+enum ZPollerSource<'a> {
+    Typed(&'a mut PollSource),
+    // Readers added as trait objects can't be handed back as their
+    // original type, so we only keep them borrowed.
+    Untyped,
+    #[cfg(unix)]
+    Fd(RawFd),
+}
 
-        if zpoller == ptr::null_mut() {
-            return Err(Error::new(ErrorKind::NullPtr, ZPollerError::Instantiate));
-        }
+struct ZPollerItem<'a> {
+    source: ZPollerSource<'a>,
+    handle: *mut c_void,
+    events: ZSockEvents,
+}
 
+/// Create a `ZPoller` from a list of readers, returning it along with
+/// their tokens in the same order.
+///
+/// ```no_run
+/// # #[macro_use] extern crate czmq;
+/// # use czmq::ZSock;
+/// # fn main() {
+/// let mut pull1 = ZSock::new_pull("@inproc://a").unwrap();
+/// let mut pull2 = ZSock::new_pull("@inproc://b").unwrap();
+/// let (mut poller, tokens) = zpoller![&mut pull1, &mut pull2].unwrap();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! zpoller {
+    ($($reader:expr),+ $(,)*) => {
+        $crate::ZPoller::new().and_then(|mut poller| {
+            let mut tokens = Vec::new();
+            $(
+                match poller.add($reader) {
+                    Ok(token) => tokens.push(token),
+                    Err(e) => return Err(e),
+                }
+            )+
+            Ok((poller, tokens))
+        })
+    };
+}
+
+/// Polls a set of readers with `zmq_poll()`. The poller borrows each
+/// reader for its whole lifetime, even after `remove()`, so use
+/// `get()` or `get_mut()` with the token returned by `add()` to reach
+/// it again, and drop the poller to get it back.
+///
+/// ```no_run
+/// # use czmq::{ZPoller, ZSock};
+/// let mut pull1 = ZSock::new_pull("@inproc://a").unwrap();
+/// let mut pull2 = ZSock::new_pull("@inproc://b").unwrap();
+///
+/// let mut poller = ZPoller::new().unwrap();
+/// let a = poller.add(&mut pull1).unwrap();
+/// poller.add(&mut pull2).unwrap();
+///
+/// for event in poller.wait(Some(500)).unwrap() {
+///     let sock: &mut ZSock = poller.get_mut(event.token).unwrap();
+///     println!("{} ready: {:?}", if event.token == a { "a" } else { "b" }, sock.recv_str());
+/// }
+/// ```
+pub struct ZPoller<'a> {
+    // Removed readers leave a hole so that tokens stay valid
+    items: Vec<Option<ZPollerItem<'a>>>,
+    expired: bool,
+    terminated: bool,
+    nonstop: bool,
+}
+
+impl<'a> ZPoller<'a> {
+    pub fn new() -> Result<ZPoller<'a>> {
         Ok(ZPoller {
-            zpoller: zpoller,
+            items: Vec::new(),
+            expired: false,
+            terminated: false,
+            nonstop: false,
         })
     }
 
-    pub fn add<S: Sockish>(&mut self, reader: &mut S) -> Result<()> {
-        let rc = unsafe { czmq_sys::zpoller_add(self.zpoller, reader.as_mut_ptr()) };
+    /// Poll `reader` for incoming messages.
+    pub fn add<S: Sockish + Any>(&mut self, reader: &'a mut S) -> Result<ZPollerToken> {
+        self.add_with(reader, ZSOCK_POLLIN)
+    }
 
-        if rc == -1 {
-            Err(Error::new(ErrorKind::NonZero, ZPollerError::CmdFailed))
-        } else {
-            Ok(())
+    /// Poll `reader` for any of `events`, e.g. `ZSOCK_POLLOUT` to wait
+    /// until it can be written to.
+    pub fn add_with<S: Sockish + Any>(&mut self, reader: &'a mut S, events: ZSockEvents) -> Result<ZPollerToken> {
+        let handle = try!(resolve(reader.as_mut_ptr()));
+        Ok(self.push(ZPollerSource::Typed(reader), handle, events))
+    }
+
+    /// Poll several readers of any type for incoming messages,
+    /// returning their tokens in the same order. As their types are
+    /// erased, they can't be borrowed back with `get()` or `get_mut()`.
+    pub fn add_all<I>(&mut self, readers: I) -> Result<Vec<ZPollerToken>>
+        where I: IntoIterator<Item = &'a mut Sockish>
+    {
+        let mut tokens = Vec::new();
+
+        for reader in readers {
+            let handle = try!(resolve(reader.as_mut_ptr()));
+            tokens.push(self.push(ZPollerSource::Untyped, handle, ZSOCK_POLLIN));
         }
+
+        Ok(tokens)
+    }
+
+    /// Poll a plain file descriptor, such as a pipe or socket, for
+    /// input.
+    #[cfg(unix)]
+    pub fn add_fd(&mut self, fd: RawFd) -> ZPollerToken {
+        self.add_fd_with(fd, ZSOCK_POLLIN)
     }
 
-    pub fn remove<S: Sockish>(&mut self, reader: &mut S) -> Result<()> {
-        let rc = unsafe { czmq_sys::zpoller_remove(self.zpoller, reader.as_mut_ptr()) };
+    #[cfg(unix)]
+    pub fn add_fd_with(&mut self, fd: RawFd, events: ZSockEvents) -> ZPollerToken {
+        self.push(ZPollerSource::Fd(fd), ptr::null_mut(), events)
+    }
 
-        if rc == -1 {
-            Err(Error::new(ErrorKind::NonZero, ZPollerError::CmdFailed))
-        } else {
-            Ok(())
+    /// Stop polling a reader. It stays borrowed until the poller is
+    /// dropped, but can no longer be reached with `get()`.
+    pub fn remove(&mut self, token: ZPollerToken) -> Result<()> {
+        match self.items.get_mut(token.0) {
+            Some(item @ &mut Some(_)) => {
+                *item = None;
+                Ok(())
+            },
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZPollerError::UnknownToken)),
+        }
+    }
+
+    /// Borrow a registered reader. Returns `None` if the token is
+    /// unknown or `S` is not the reader's type.
+    pub fn get<S: Sockish + Any>(&self, token: ZPollerToken) -> Option<&S> {
+        match self.items.get(token.0) {
+            Some(&Some(ZPollerItem { source: ZPollerSource::Typed(ref source), .. })) => source.as_any().downcast_ref(),
+            _ => None,
         }
     }
 
-    pub fn wait<S: Sockish>(&self, timeout: Option<u32>) -> Option<S> {
-        let t = match timeout {
-            Some(time) => time as c_int,
-            None => -1 as c_int,
+    pub fn get_mut<S: Sockish + Any>(&mut self, token: ZPollerToken) -> Option<&mut S> {
+        match self.items.get_mut(token.0) {
+            Some(&mut Some(ZPollerItem { source: ZPollerSource::Typed(ref mut source), .. })) => source.as_any_mut().downcast_mut(),
+            _ => None,
+        }
+    }
+
+    /// Wait up to `timeout` milliseconds, or forever if `None`, for
+    /// any readers to become ready. Returns every ready reader; if
+    /// there are none, check `expired()` and `terminated()` to see
+    /// why.
+    pub fn wait(&mut self, timeout: Option<u32>) -> Result<Vec<ZPollerEvent>> {
+        self.expired = false;
+        self.terminated = false;
+
+        let mut tokens = Vec::new();
+        let mut pollitems = Vec::new();
+
+        for (i, item) in self.items.iter().enumerate() {
+            if let Some(ref item) = *item {
+                tokens.push(ZPollerToken(i));
+                let fd = match item.source {
+                    #[cfg(unix)]
+                    ZPollerSource::Fd(fd) => fd,
+                    _ => 0,
+                };
+
+                pollitems.push(czmq_sys::zmq_pollitem_t {
+                    socket: item.handle,
+                    fd: fd,
+                    events: item.events.bits() as c_short,
+                    revents: 0,
+                });
+            }
+        }
+
+        let timeout = match timeout {
+            Some(t) => t as c_long,
+            None => -1,
         };
 
-        let ptr = unsafe { czmq_sys::zpoller_wait(self.zpoller, t) };
+        let rc = unsafe { czmq_sys::zmq_poll(pollitems.as_mut_ptr(), pollitems.len() as i32, timeout) };
+
+        if rc == -1 {
+            match zmq::Error::from_raw(unsafe { czmq_sys::zmq_errno() }) {
+                zmq::Error::EINTR | zmq::Error::ETERM => (),
+                e => return Err(Error::new(ErrorKind::NonZero, e)),
+            }
+        }
+
+        if rc == -1 || (!self.nonstop && unsafe { czmq_sys::zsys_interrupted } == 1) {
+            self.terminated = true;
+            return Ok(Vec::new());
+        }
 
-        if ptr == ptr::null_mut() {
-            None
-        } else {
-            Some(unsafe { S::from_raw(ptr, false) })
+        if rc == 0 {
+            self.expired = true;
+            return Ok(Vec::new());
         }
+
+        Ok(tokens.into_iter()
+            .zip(pollitems.iter())
+            .filter(|&(_, item)| item.revents != 0)
+            .map(|(token, item)| ZPollerEvent {
+                token: token,
+                events: ZSockEvents::from_bits_truncate(item.revents as i32),
+            })
+            .collect())
+    }
+
+    fn push(&mut self, source: ZPollerSource<'a>, handle: *mut c_void, events: ZSockEvents) -> ZPollerToken {
+        self.items.push(Some(ZPollerItem {
+            source: source,
+            handle: handle,
+            events: events,
+        }));
+
+        ZPollerToken(self.items.len() - 1)
     }
 
+    /// Whether the last `wait()` timed out.
     pub fn expired(&self) -> bool {
-        unsafe { czmq_sys::zpoller_expired(self.zpoller) == 1 }
+        self.expired
     }
 
+    /// Whether the last `wait()` was interrupted, either by a signal
+    /// or because the context was shut down.
     pub fn terminated(&self) -> bool {
-        unsafe { czmq_sys::zpoller_terminated(self.zpoller) == 1 }
+        self.terminated
+    }
+
+    /// Ignore SIGINT/SIGTERM, so that `wait()` only stops on a ready
+    /// reader, a timeout or context shutdown.
+    pub fn set_nonstop(&mut self, nonstop: bool) {
+        self.nonstop = nonstop;
     }
+}
+
+// zmq_poll() needs the libzmq socket rather than the CZMQ wrapper.
+fn resolve(ptr: *mut c_void) -> Result<*mut c_void> {
+    let handle = unsafe { czmq_sys::zsock_resolve(ptr) };
 
-    pub fn set_nonstop(&self, nonstop: bool) {
-        unsafe { czmq_sys::zpoller_set_nonstop(self.zpoller, if nonstop { 1 } else { 0 }) }
+    if handle == ptr::null_mut() {
+        Err(Error::new(ErrorKind::InvalidArg, ZPollerError::Resolve))
+    } else {
+        Ok(handle)
     }
 }
 
 #[derive(Debug)]
 pub enum ZPollerError {
-    CmdFailed,
-    Instantiate,
+    Resolve,
+    UnknownToken,
 }
 
 impl fmt::Display for ZPollerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            ZPollerError::CmdFailed => write!(f, "ZPoller command failed"),
-            ZPollerError::Instantiate => write!(f, "Could not instantiate new ZPoller struct"),
+            ZPollerError::Resolve => write!(f, "Reader is not a ZeroMQ socket"),
+            ZPollerError::UnknownToken => write!(f, "Token does not belong to a reader in this ZPoller"),
         }
     }
 }
@@ -97,15 +305,21 @@ impl fmt::Display for ZPollerError {
 impl error::Error for ZPollerError {
     fn description(&self) -> &str {
         match *self {
-            ZPollerError::CmdFailed => "ZPoller command failed",
-            ZPollerError::Instantiate => "Could not instantiate new ZPoller struct",
+            ZPollerError::Resolve => "Reader is not a ZeroMQ socket",
+            ZPollerError::UnknownToken => "Token does not belong to a reader in this ZPoller",
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use {ZSock, SocketType, ZSys};
+    #[cfg(unix)]
+    use std::io::{Read, Write};
+    #[cfg(unix)]
+    use std::os::unix::io::AsRawFd;
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
+    use {Sockish, SocketType, ZActor, ZSock, ZSys, ZSOCK_POLLIN, ZSOCK_POLLOUT};
     use super::*;
 
     #[test]
@@ -119,8 +333,10 @@ mod tests {
 
         let mut sock = ZSock::new(SocketType::PAIR);
         let mut poller = ZPoller::new().unwrap();
-        assert!(poller.add(&mut sock).is_ok());
-        assert!(poller.remove(&mut sock).is_ok());
+        let token = poller.add(&mut sock).unwrap();
+        assert!(poller.remove(token).is_ok());
+        assert!(poller.remove(token).is_err());
+        assert!(poller.get::<ZSock>(token).is_none());
     }
 
     #[test]
@@ -133,23 +349,138 @@ mod tests {
 
         let mut server2 = ZSock::new_rep("inproc://zpoller_test_wait2").unwrap();
         let client2 = ZSock::new_req("inproc://zpoller_test_wait2").unwrap();
-        client2.send_str("cow").unwrap();
 
         let mut poller = ZPoller::new().unwrap();
-        poller.add(&mut server1).unwrap();
-        poller.add(&mut server2).unwrap();
+        let token1 = poller.add(&mut server1).unwrap();
+        let token2 = poller.add(&mut server2).unwrap();
 
-        let sock: ZSock = poller.wait(Some(500)).unwrap();
+        let ready = poller.wait(Some(500)).unwrap();
+        assert_eq!(ready, vec![ZPollerEvent { token: token1, events: ZSOCK_POLLIN }]);
+        let sock: &mut ZSock = poller.get_mut(token1).unwrap();
         assert_eq!(sock.endpoint().unwrap(), "inproc://zpoller_test_wait1");
-        server1.recv_str().unwrap().unwrap();
+        sock.recv_str().unwrap().unwrap();
 
-        let sock: ZSock = poller.wait(Some(500)).unwrap();
-        assert_eq!(sock.endpoint().unwrap(), "inproc://zpoller_test_wait2");
-        server2.recv_str().unwrap().unwrap();
+        client2.send_str("cow").unwrap();
+        let ready = poller.wait(Some(500)).unwrap();
+        assert_eq!(ready, vec![ZPollerEvent { token: token2, events: ZSOCK_POLLIN }]);
+        poller.get_mut::<ZSock>(token2).unwrap().recv_str().unwrap().unwrap();
 
-        let sock: Option<ZSock> = poller.wait(Some(0));
-        assert!(sock.is_none());
+        assert!(poller.wait(Some(0)).unwrap().is_empty());
         assert!(poller.expired());
         assert!(!poller.terminated());
     }
+
+    #[test]
+    fn test_wait_many() {
+        ZSys::init();
+
+        let mut server1 = ZSock::new_pull("inproc://zpoller_test_wait_many1").unwrap();
+        let client1 = ZSock::new_push("inproc://zpoller_test_wait_many1").unwrap();
+        let mut server2 = ZSock::new_pull("inproc://zpoller_test_wait_many2").unwrap();
+        let client2 = ZSock::new_push("inproc://zpoller_test_wait_many2").unwrap();
+        client1.send_str("moo").unwrap();
+        client2.send_str("cow").unwrap();
+
+        let mut poller = ZPoller::new().unwrap();
+        let token1 = poller.add(&mut server1).unwrap();
+        let token2 = poller.add(&mut server2).unwrap();
+
+        let ready: Vec<_> = poller.wait(Some(500)).unwrap().into_iter().map(|e| e.token).collect();
+        assert_eq!(ready, vec![token1, token2]);
+    }
+
+    #[test]
+    fn test_write_interest() {
+        ZSys::init();
+
+        let mut push = ZSock::new_push("@inproc://zpoller_test_write_interest").unwrap();
+
+        let mut poller = ZPoller::new().unwrap();
+        let token = poller.add_with(&mut push, ZSOCK_POLLOUT).unwrap();
+
+        // A PUSH socket with no peers can't be written to
+        assert!(poller.wait(Some(0)).unwrap().is_empty());
+
+        let _pull = ZSock::new_pull(">inproc://zpoller_test_write_interest").unwrap();
+        let ready = poller.wait(Some(500)).unwrap();
+        assert_eq!(ready, vec![ZPollerEvent { token: token, events: ZSOCK_POLLOUT }]);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        ZSys::init();
+
+        let mut actor = ZActor::spawn(|mut pipe| {
+            while let Ok(Some(_)) = ZActor::recv_cmd(&mut pipe) {}
+        }).unwrap();
+
+        let mut poller = ZPoller::new().unwrap();
+        let token = poller.add(&mut actor).unwrap();
+        assert!(poller.get::<ZSock>(token).is_none());
+        assert!(poller.get::<ZActor>(token).is_some());
+    }
+
+    #[test]
+    fn test_macro() {
+        ZSys::init();
+
+        let mut server1 = ZSock::new_pull("inproc://zpoller_test_macro1").unwrap();
+        let mut server2 = ZSock::new_pull("inproc://zpoller_test_macro2").unwrap();
+        let client2 = ZSock::new_push("inproc://zpoller_test_macro2").unwrap();
+        client2.send_str("moo").unwrap();
+
+        let (mut poller, tokens) = zpoller![&mut server1, &mut server2].unwrap();
+        assert_eq!(tokens.len(), 2);
+
+        let ready = poller.wait(Some(500)).unwrap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].token, tokens[1]);
+        assert_eq!(poller.get_mut::<ZSock>(tokens[1]).unwrap().recv_str().unwrap().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_add_all() {
+        ZSys::init();
+
+        let mut server = ZSock::new_pull("inproc://zpoller_test_add_all").unwrap();
+        let client = ZSock::new_push("inproc://zpoller_test_add_all").unwrap();
+        let mut actor = ZActor::spawn(|mut pipe| {
+            while let Ok(Some(_)) = ZActor::recv_cmd(&mut pipe) {}
+        }).unwrap();
+        client.send_str("moo").unwrap();
+
+        let readers: Vec<&mut Sockish> = vec![&mut actor, &mut server];
+        let mut poller = ZPoller::new().unwrap();
+        let tokens = poller.add_all(readers).unwrap();
+
+        let ready = poller.wait(Some(500)).unwrap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].token, tokens[1]);
+        assert!(poller.get::<ZSock>(tokens[1]).is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_add_fd() {
+        ZSys::init();
+
+        let (mut tx, mut rx) = UnixStream::pair().unwrap();
+        let mut server = ZSock::new_pull("inproc://zpoller_test_add_fd").unwrap();
+        let client = ZSock::new_push("inproc://zpoller_test_add_fd").unwrap();
+
+        let mut poller = ZPoller::new().unwrap();
+        let fd_token = poller.add_fd(rx.as_raw_fd());
+        let sock_token = poller.add(&mut server).unwrap();
+        assert!(poller.wait(Some(0)).unwrap().is_empty());
+
+        tx.write_all(b"x").unwrap();
+        client.send_str("moo").unwrap();
+
+        let ready: Vec<_> = poller.wait(Some(500)).unwrap().into_iter().map(|e| e.token).collect();
+        assert_eq!(ready, vec![fd_token, sock_token]);
+        assert!(poller.get::<ZSock>(fd_token).is_none());
+
+        let mut buf = [0; 1];
+        rx.read_exact(&mut buf).unwrap();
+    }
 }
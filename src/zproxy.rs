@@ -0,0 +1,300 @@
+//! Module: czmq-zproxy
+
+use {czmq_sys, Error, ErrorKind, RawInterface, Result, SocketType, ZActor, ZCert, ZMsg};
+use std::{error, fmt, ptr};
+use std::os::raw::c_void;
+use zactor::{pop_cmd_str, ZActorError, ZActorMessage};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ZProxySide {
+    Frontend,
+    Backend,
+}
+
+impl ZProxySide {
+    pub fn to_str(&self) -> &'static str {
+        match *self {
+            ZProxySide::Frontend => "FRONTEND",
+            ZProxySide::Backend => "BACKEND",
+        }
+    }
+
+    pub fn from_str(side: &str) -> Option<ZProxySide> {
+        match side {
+            "FRONTEND" => Some(ZProxySide::Frontend),
+            "BACKEND" => Some(ZProxySide::Backend),
+            _ => None,
+        }
+    }
+}
+
+pub struct ZProxy {
+    zactor: ZActor,
+}
+
+unsafe impl Send for ZProxy {}
+
+impl ZProxy {
+    pub fn new() -> Result<ZProxy> {
+        let zactor = unsafe { czmq_sys::zactor_new(czmq_sys::zproxy, ptr::null_mut()) };
+
+        if zactor == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZProxyError::Instantiate))
+        } else {
+            Ok(ZProxy {
+                zactor: unsafe { ZActor::from_raw(zactor as *mut c_void, true) },
+            })
+        }
+    }
+
+    /// Create the frontend socket. `endpoints` is a comma-separated
+    /// list, which binds by default unless prefixed with '>'.
+    pub fn set_frontend(&self, sock_type: SocketType, endpoints: &str) -> Result<()> {
+        self.command(ZProxyCommand::Socket(ZProxySide::Frontend, sock_type, endpoints.to_owned()))
+    }
+
+    /// Create the backend socket. `endpoints` is a comma-separated
+    /// list, which binds by default unless prefixed with '>'.
+    pub fn set_backend(&self, sock_type: SocketType, endpoints: &str) -> Result<()> {
+        self.command(ZProxyCommand::Socket(ZProxySide::Backend, sock_type, endpoints.to_owned()))
+    }
+
+    /// Send a copy of all proxied traffic to a PULL socket bound at
+    /// `endpoint`.
+    pub fn set_capture(&self, endpoint: &str) -> Result<()> {
+        self.command(ZProxyCommand::Capture(endpoint.to_owned()))
+    }
+
+    pub fn pause(&self) -> Result<()> {
+        self.command(ZProxyCommand::Pause)
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.command(ZProxyCommand::Resume)
+    }
+
+    pub fn verbose(&self) -> Result<()> {
+        self.command(ZProxyCommand::Verbose)
+    }
+
+    // The security settings only take effect for sockets created
+    // afterwards, so call these before set_frontend()/set_backend().
+
+    pub fn set_domain(&self, side: ZProxySide, domain: &str) -> Result<()> {
+        self.command(ZProxyCommand::Domain(side, domain.to_owned()))
+    }
+
+    pub fn set_plain(&self, side: ZProxySide) -> Result<()> {
+        self.command(ZProxyCommand::Plain(side))
+    }
+
+    pub fn set_curve(&self, side: ZProxySide, cert: &ZCert) -> Result<()> {
+        self.command(ZProxyCommand::Curve(side, cert.public_txt().to_owned(), cert.secret_txt().to_owned()))
+    }
+
+    // Every zproxy command is acknowledged with a signal.
+    fn command(&self, cmd: ZProxyCommand) -> Result<()> {
+        try!(self.zactor.send_cmd(&cmd));
+        self.zactor.sock().wait()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ZProxyCommand {
+    Socket(ZProxySide, SocketType, String),
+    Capture(String),
+    Pause,
+    Resume,
+    Verbose,
+    Domain(ZProxySide, String),
+    Plain(ZProxySide),
+    Curve(ZProxySide, String, String),
+}
+
+impl ZActorMessage for ZProxyCommand {
+    fn encode(&self) -> Result<ZMsg> {
+        let msg = ZMsg::new();
+
+        match *self {
+            ZProxyCommand::Socket(side, sock_type, ref endpoints) => {
+                try!(msg.addstr(side.to_str()));
+                try!(msg.addstr(sock_type_to_str(sock_type)));
+                try!(msg.addstr(endpoints));
+            },
+            ZProxyCommand::Capture(ref endpoint) => {
+                try!(msg.addstr("CAPTURE"));
+                try!(msg.addstr(endpoint));
+            },
+            ZProxyCommand::Pause => try!(msg.addstr("PAUSE")),
+            ZProxyCommand::Resume => try!(msg.addstr("RESUME")),
+            ZProxyCommand::Verbose => try!(msg.addstr("VERBOSE")),
+            ZProxyCommand::Domain(side, ref domain) => {
+                try!(msg.addstr("DOMAIN"));
+                try!(msg.addstr(side.to_str()));
+                try!(msg.addstr(domain));
+            },
+            ZProxyCommand::Plain(side) => {
+                try!(msg.addstr("PLAIN"));
+                try!(msg.addstr(side.to_str()));
+            },
+            ZProxyCommand::Curve(side, ref public_txt, ref secret_txt) => {
+                try!(msg.addstr("CURVE"));
+                try!(msg.addstr(side.to_str()));
+                try!(msg.addstr(public_txt));
+                try!(msg.addstr(secret_txt));
+            },
+        }
+
+        Ok(msg)
+    }
+
+    fn decode(msg: ZMsg) -> Result<ZProxyCommand> {
+        let command = try!(pop_cmd_str(&msg));
+
+        if let Some(side) = ZProxySide::from_str(&command) {
+            let sock_type = try!(sock_type_from_str(&try!(pop_cmd_str(&msg))));
+            return Ok(ZProxyCommand::Socket(side, sock_type, try!(pop_cmd_str(&msg))));
+        }
+
+        match command.as_ref() {
+            "CAPTURE" => Ok(ZProxyCommand::Capture(try!(pop_cmd_str(&msg)))),
+            "PAUSE" => Ok(ZProxyCommand::Pause),
+            "RESUME" => Ok(ZProxyCommand::Resume),
+            "VERBOSE" => Ok(ZProxyCommand::Verbose),
+            "DOMAIN" => {
+                let side = try!(pop_side(&msg));
+                Ok(ZProxyCommand::Domain(side, try!(pop_cmd_str(&msg))))
+            },
+            "PLAIN" => Ok(ZProxyCommand::Plain(try!(pop_side(&msg)))),
+            "CURVE" => {
+                let side = try!(pop_side(&msg));
+                let public_txt = try!(pop_cmd_str(&msg));
+                Ok(ZProxyCommand::Curve(side, public_txt, try!(pop_cmd_str(&msg))))
+            },
+            _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+        }
+    }
+}
+
+fn pop_side(msg: &ZMsg) -> Result<ZProxySide> {
+    ZProxySide::from_str(&try!(pop_cmd_str(msg)))
+        .ok_or(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand))
+}
+
+// zproxy creates its sockets from these names rather than from the
+// libzmq constants.
+fn sock_type_to_str(sock_type: SocketType) -> &'static str {
+    match sock_type {
+        SocketType::PAIR => "PAIR",
+        SocketType::PUB => "PUB",
+        SocketType::SUB => "SUB",
+        SocketType::REQ => "REQ",
+        SocketType::REP => "REP",
+        SocketType::DEALER => "DEALER",
+        SocketType::ROUTER => "ROUTER",
+        SocketType::PULL => "PULL",
+        SocketType::PUSH => "PUSH",
+        SocketType::XPUB => "XPUB",
+        SocketType::XSUB => "XSUB",
+        SocketType::STREAM => "STREAM",
+    }
+}
+
+fn sock_type_from_str(name: &str) -> Result<SocketType> {
+    match name {
+        "PAIR" => Ok(SocketType::PAIR),
+        "PUB" => Ok(SocketType::PUB),
+        "SUB" => Ok(SocketType::SUB),
+        "REQ" => Ok(SocketType::REQ),
+        "REP" => Ok(SocketType::REP),
+        "DEALER" => Ok(SocketType::DEALER),
+        "ROUTER" => Ok(SocketType::ROUTER),
+        "PULL" => Ok(SocketType::PULL),
+        "PUSH" => Ok(SocketType::PUSH),
+        "XPUB" => Ok(SocketType::XPUB),
+        "XSUB" => Ok(SocketType::XSUB),
+        "STREAM" => Ok(SocketType::STREAM),
+        _ => Err(Error::new(ErrorKind::InvalidArg, ZActorError::BadCommand)),
+    }
+}
+
+#[derive(Debug)]
+pub enum ZProxyError {
+    Instantiate,
+}
+
+impl fmt::Display for ZProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZProxyError::Instantiate => write!(f, "Could not instantiate new ZProxy struct"),
+        }
+    }
+}
+
+impl error::Error for ZProxyError {
+    fn description(&self) -> &str {
+        match *self {
+            ZProxyError::Instantiate => "Could not instantiate new ZProxy struct",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {SocketType, ZSock, ZSys};
+    use super::*;
+
+    #[test]
+    fn test_command_roundtrip() {
+        let cmds = vec![
+            ZProxyCommand::Socket(ZProxySide::Backend, SocketType::DEALER, "inproc://a,inproc://b".into()),
+            ZProxyCommand::Pause,
+            ZProxyCommand::Curve(ZProxySide::Frontend, "pub".into(), "sec".into()),
+        ];
+
+        for cmd in cmds {
+            assert_eq!(ZProxyCommand::decode(cmd.encode().unwrap()).unwrap(), cmd);
+        }
+    }
+
+    #[test]
+    fn test_proxy() {
+        ZSys::init();
+
+        let proxy = ZProxy::new().unwrap();
+        proxy.set_frontend(SocketType::PULL, "inproc://zproxy_test_proxy_front").unwrap();
+        proxy.set_backend(SocketType::PUSH, "inproc://zproxy_test_proxy_back").unwrap();
+
+        let capture = ZSock::new_pull("inproc://zproxy_test_proxy_capture").unwrap();
+        capture.set_rcvtimeo(Some(500));
+        proxy.set_capture("inproc://zproxy_test_proxy_capture").unwrap();
+
+        let client = ZSock::new_push(">inproc://zproxy_test_proxy_front").unwrap();
+        let worker = ZSock::new_pull(">inproc://zproxy_test_proxy_back").unwrap();
+        worker.set_rcvtimeo(Some(500));
+
+        client.send_str("moo").unwrap();
+        assert_eq!(worker.recv_str().unwrap().unwrap(), "moo");
+        assert_eq!(capture.recv_str().unwrap().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        ZSys::init();
+
+        let proxy = ZProxy::new().unwrap();
+        proxy.set_frontend(SocketType::PULL, "inproc://zproxy_test_pause_front").unwrap();
+        proxy.set_backend(SocketType::PUSH, "inproc://zproxy_test_pause_back").unwrap();
+
+        let client = ZSock::new_push(">inproc://zproxy_test_pause_front").unwrap();
+        let worker = ZSock::new_pull(">inproc://zproxy_test_pause_back").unwrap();
+        worker.set_rcvtimeo(Some(200));
+
+        proxy.pause().unwrap();
+        client.send_str("moo").unwrap();
+        assert!(worker.recv_str().is_err());
+
+        proxy.resume().unwrap();
+        assert_eq!(worker.recv_str().unwrap().unwrap(), "moo");
+    }
+}
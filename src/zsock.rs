@@ -1,10 +1,35 @@
 //! Module: czmq-zsock
 
-use {czmq_sys, Error, ErrorKind, RawInterface, Result, Sockish, ZMonitor};
+use {czmq_sys, duration_ms, Error, ErrorKind, RawInterface, RecvBinaryPicture, RecvPicture, Result, SendBinaryPicture, SendPicture, Sockish, ZMonitor, ZMsg};
 use std::{error, fmt, mem, ptr, result};
 use std::ffi::{CStr, CString};
-use std::os::raw::c_void;
-use zmq::{Mechanism, SocketType};
+use std::os::raw::{c_char, c_void};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+use zmq::{self, Mechanism, SocketType};
+
+bitflags! {
+    pub flags ZSockEvents: i32 {
+        const ZSOCK_POLLIN  = 0b00000001,
+        const ZSOCK_POLLOUT = 0b00000010,
+        const ZSOCK_POLLERR = 0b00000100,
+    }
+}
+
+// The zmq crate's SocketType only covers the stable socket types, so
+// the draft types get their own enum.
+#[cfg(feature = "draft")]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DraftSocketType {
+    SERVER = 12,
+    CLIENT = 13,
+    RADIO = 14,
+    DISH = 15,
+    GATHER = 16,
+    SCATTER = 17,
+}
 
 pub struct ZSock {
     zsock: *mut czmq_sys::zsock_t,
@@ -35,6 +60,10 @@ impl ZSock {
         }
     }
 
+    pub fn builder(sock_type: SocketType) -> ZSockBuilder {
+        ZSockBuilder::new(sock_type)
+    }
+
     pub fn new_pub(endpoint: &str) -> Result<ZSock> {
         let zsock = unsafe { czmq_sys::zsock_new_pub(CString::new(endpoint).unwrap().as_ptr()) };
 
@@ -200,6 +229,100 @@ impl ZSock {
         }
     }
 
+    #[cfg(feature = "draft")]
+    pub fn new_server(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_server(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn new_client(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_client(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn new_radio(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_radio(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn new_dish(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_dish(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn new_gather(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_gather(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn new_scatter(endpoint: &str) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new_scatter(CString::new(endpoint).unwrap().as_ptr()) };
+
+        if zsock == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock))
+        } else {
+            Ok(ZSock {
+                zsock: zsock,
+                owned: true,
+            })
+        }
+    }
+
+    /// Create a socket of a draft type without binding or connecting
+    /// it, like `ZSock::new()`.
+    #[cfg(feature = "draft")]
+    pub fn new_draft(sock_type: DraftSocketType) -> ZSock {
+        ZSock {
+            zsock: unsafe { czmq_sys::zsock_new(sock_type as i32) },
+            owned: true,
+        }
+    }
+
     pub fn bind(&self, endpoint: &str) -> Result<i32> {
         let rc = unsafe { czmq_sys::zsock_bind(self.zsock, "%s\0".as_ptr() as *const i8, try!(CString::new(endpoint)).as_ptr() as *const i8) };
         if rc == -1 {
@@ -302,13 +425,72 @@ impl ZSock {
         }
     }
 
-    // pub fn zsock_bsend(_self: *mut ::std::os::raw::c_void,
-    //                    picture: *const ::std::os::raw::c_char, ...)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_brecv(_self: *mut ::std::os::raw::c_void,
-    //                    picture: *const ::std::os::raw::c_char, ...)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_unbounded(_self: *mut ::std::os::raw::c_void);
+    /// Send a multi-frame message described by a picture, which is
+    /// the first element of the tuple, e.g. `("sbi", name, bytes, 42)`.
+    /// See the picture module for the supported characters.
+    pub fn send_picture<P: SendPicture>(&self, picture: P) -> Result<()> {
+        let msg = try!(picture.to_msg());
+
+        let rc = unsafe { czmq_sys::zmsg_send(&mut msg.into_raw(), self.zsock as *mut c_void) };
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Receive a message into a tuple, using each type's default
+    /// picture character, e.g. (String, Vec<u8>, i32) receives "sbi".
+    pub fn recv_picture<T: RecvPicture>(&self) -> Result<T> {
+        self.recv_picture_as(&T::picture())
+    }
+
+    /// Receive a message into a tuple using an explicit picture, for
+    /// types with more than one encoding, e.g. u32 as '4' or 'u'.
+    pub fn recv_picture_as<T: RecvPicture>(&self, picture: &str) -> Result<T> {
+        let ptr = unsafe { czmq_sys::zmsg_recv(self.zsock as *mut c_void) };
+
+        if ptr == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CmdFailed))
+        } else {
+            let msg = unsafe { ZMsg::from_raw(ptr, true) };
+            T::from_msg(picture, &msg)
+        }
+    }
+
+    /// Send a binary picture, which packs everything except frames and
+    /// messages into a single frame, e.g. `("1sS", 1u8, name, body)`.
+    /// Compatible with zsock_bsend().
+    pub fn bsend<P: SendBinaryPicture>(&self, picture: P) -> Result<()> {
+        let msg = try!(picture.to_bmsg());
+
+        let rc = unsafe { czmq_sys::zmsg_send(&mut msg.into_raw(), self.zsock as *mut c_void) };
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Receive a binary picture sent by `bsend()` or zsock_bsend().
+    /// Truncated frames, unread trailing bytes and missing frames are
+    /// errors.
+    pub fn brecv<T: RecvBinaryPicture>(&self, picture: &str) -> Result<T> {
+        let ptr = unsafe { czmq_sys::zmsg_recv(self.zsock as *mut c_void) };
+
+        if ptr == ptr::null_mut() {
+            Err(Error::new(ErrorKind::NullPtr, ZSockError::CmdFailed))
+        } else {
+            let msg = unsafe { ZMsg::from_raw(ptr, true) };
+            T::from_bmsg(picture, &msg)
+        }
+    }
+
+    /// Remove the send and receive high water marks, so messages
+    /// are never dropped or blocked. Use with care.
+    pub fn set_unbounded(&self) {
+        unsafe { czmq_sys::zsock_set_unbounded(self.zsock as *mut c_void) };
+    }
 
     pub fn signal(&self, status: u8) -> Result<()> {
         let rc = unsafe { czmq_sys::zsock_signal(self.zsock as *mut c_void, status) };
@@ -335,23 +517,50 @@ impl ZSock {
     // pub fn zsock_is(_self: *mut ::std::os::raw::c_void) -> u8;
     // pub fn zsock_resolve(_self: *mut ::std::os::raw::c_void)
     //  -> *mut ::std::os::raw::c_void;
-    // pub fn zsock_tos(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_tos(_self: *mut ::std::os::raw::c_void,
-    //                      tos: ::std::os::raw::c_int);
-    // pub fn zsock_set_router_handover(_self: *mut ::std::os::raw::c_void,
-    //                                  router_handover: ::std::os::raw::c_int);
-    // pub fn zsock_set_router_mandatory(_self: *mut ::std::os::raw::c_void,
-    //                                   router_mandatory:
-    //                                       ::std::os::raw::c_int);
-    // pub fn zsock_set_probe_router(_self: *mut ::std::os::raw::c_void,
-    //                               probe_router: ::std::os::raw::c_int);
-    // pub fn zsock_set_req_relaxed(_self: *mut ::std::os::raw::c_void,
-    //                              req_relaxed: ::std::os::raw::c_int);
-    // pub fn zsock_set_req_correlate(_self: *mut ::std::os::raw::c_void,
-    //                                req_correlate: ::std::os::raw::c_int);
-    // pub fn zsock_set_conflate(_self: *mut ::std::os::raw::c_void,
-    //                           conflate: ::std::os::raw::c_int);
+    pub fn tos(&self) -> Result<i32> {
+        let tos = unsafe { czmq_sys::zsock_tos(self.zsock as *mut c_void) };
+
+        if tos == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(tos)
+        }
+    }
+
+    pub fn set_tos(&self, tos: i32) {
+        unsafe { czmq_sys::zsock_set_tos(self.zsock as *mut c_void, tos) };
+    }
+
+    /// Let a new ROUTER peer take over an identity that is already
+    /// connected, rather than being ignored.
+    pub fn set_router_handover(&self, router_handover: bool) {
+        unsafe { czmq_sys::zsock_set_router_handover(self.zsock as *mut c_void, if router_handover { 1 } else { 0 }) };
+    }
+
+    /// Fail sends to unroutable identities rather than dropping them.
+    pub fn set_router_mandatory(&self, router_mandatory: bool) {
+        unsafe { czmq_sys::zsock_set_router_mandatory(self.zsock as *mut c_void, if router_mandatory { 1 } else { 0 }) };
+    }
+
+    /// Send an empty message to ROUTER peers on connect.
+    pub fn set_probe_router(&self, probe_router: bool) {
+        unsafe { czmq_sys::zsock_set_probe_router(self.zsock as *mut c_void, if probe_router { 1 } else { 0 }) };
+    }
+
+    /// Allow a REQ socket to send again without waiting for a reply.
+    pub fn set_req_relaxed(&self, req_relaxed: bool) {
+        unsafe { czmq_sys::zsock_set_req_relaxed(self.zsock as *mut c_void, if req_relaxed { 1 } else { 0 }) };
+    }
+
+    /// Tag REQ requests with an id so replies can be matched to them.
+    pub fn set_req_correlate(&self, req_correlate: bool) {
+        unsafe { czmq_sys::zsock_set_req_correlate(self.zsock as *mut c_void, if req_correlate { 1 } else { 0 }) };
+    }
+
+    /// Keep only the most recent message in the queue.
+    pub fn set_conflate(&self, conflate: bool) {
+        unsafe { czmq_sys::zsock_set_conflate(self.zsock as *mut c_void, if conflate { 1 } else { 0 }) };
+    }
 
     pub fn zap_domain<'a>(&'a self) -> Result<result::Result<String, Vec<u8>>> {
         let domain = unsafe { czmq_sys::zsock_zap_domain(self.zsock as *mut c_void) };
@@ -565,16 +774,26 @@ impl ZSock {
     //                                               *mut ::std::os::raw::c_void,
     //                                           gssapi_service_principal:
     //                                               *const ::std::os::raw::c_char);
-    // pub fn zsock_ipv6(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_ipv6(_self: *mut ::std::os::raw::c_void,
-    //                       ipv6: ::std::os::raw::c_int);
-    // pub fn zsock_immediate(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_immediate(_self: *mut ::std::os::raw::c_void,
-    //                            immediate: ::std::os::raw::c_int);
-    // pub fn zsock_set_router_raw(_self: *mut ::std::os::raw::c_void,
-    //                             router_raw: ::std::os::raw::c_int);
+    pub fn ipv6(&self) -> bool {
+        unsafe { czmq_sys::zsock_ipv6(self.zsock as *mut c_void) == 1 }
+    }
+
+    pub fn set_ipv6(&self, ipv6: bool) {
+        unsafe { czmq_sys::zsock_set_ipv6(self.zsock as *mut c_void, if ipv6 { 1 } else { 0 }) };
+    }
+
+    pub fn immediate(&self) -> bool {
+        unsafe { czmq_sys::zsock_immediate(self.zsock as *mut c_void) == 1 }
+    }
+
+    pub fn set_immediate(&self, immediate: bool) {
+        unsafe { czmq_sys::zsock_set_immediate(self.zsock as *mut c_void, if immediate { 1 } else { 0 }) };
+    }
+
+    /// Exchange raw TCP data on a ROUTER socket, framed by peer identity.
+    pub fn set_router_raw(&self, router_raw: bool) {
+        unsafe { czmq_sys::zsock_set_router_raw(self.zsock as *mut c_void, if router_raw { 1 } else { 0 }) };
+    }
     // pub fn zsock_ipv4only(_self: *mut ::std::os::raw::c_void)
     //  -> ::std::os::raw::c_int;
     // pub fn zsock_set_ipv4only(_self: *mut ::std::os::raw::c_void,
@@ -604,6 +823,61 @@ impl ZSock {
         }
     }
 
+    /// Returns the socket type if it's one of the draft types, which
+    /// `zsock_type()` can't represent.
+    #[cfg(feature = "draft")]
+    pub fn draft_type(&self) -> Option<DraftSocketType> {
+        let stype = unsafe { czmq_sys::zsock_type(self.zsock as *mut c_void) };
+
+        match stype {
+            12 => Some(DraftSocketType::SERVER),
+            13 => Some(DraftSocketType::CLIENT),
+            14 => Some(DraftSocketType::RADIO),
+            15 => Some(DraftSocketType::DISH),
+            16 => Some(DraftSocketType::GATHER),
+            17 => Some(DraftSocketType::SCATTER),
+            _ => None,
+        }
+    }
+
+    /// The routing id of the last message received on a SERVER socket
+    /// via the picture API.
+    #[cfg(feature = "draft")]
+    pub fn routing_id(&self) -> u32 {
+        unsafe { czmq_sys::zsock_routing_id(self.zsock) }
+    }
+
+    /// Set the routing id used by the picture API when sending on a
+    /// SERVER socket.
+    #[cfg(feature = "draft")]
+    pub fn set_routing_id(&self, routing_id: u32) {
+        unsafe { czmq_sys::zsock_set_routing_id(self.zsock, routing_id) };
+    }
+
+    /// Join a group on a DISH socket, to receive messages that RADIO
+    /// sockets send to it.
+    #[cfg(feature = "draft")]
+    pub fn join(&self, group: &str) -> Result<()> {
+        let group_c = try!(CString::new(group));
+        let rc = unsafe { czmq_sys::zsock_join(self.zsock as *mut c_void, group_c.as_ptr()) };
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "draft")]
+    pub fn leave(&self, group: &str) -> Result<()> {
+        let group_c = try!(CString::new(group));
+        let rc = unsafe { czmq_sys::zsock_leave(self.zsock as *mut c_void, group_c.as_ptr()) };
+        if rc == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn sndhwm(&self) -> Result<i32> {
         let sndhwm = unsafe { czmq_sys::zsock_sndhwm(self.zsock as *mut c_void) };
 
@@ -632,10 +906,19 @@ impl ZSock {
         unsafe { czmq_sys::zsock_set_rcvhwm(self.zsock as *mut c_void, rcvhwm) };
     }
 
-    // pub fn zsock_affinity(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_affinity(_self: *mut ::std::os::raw::c_void,
-    //                           affinity: ::std::os::raw::c_int);
+    pub fn affinity(&self) -> Result<i32> {
+        let affinity = unsafe { czmq_sys::zsock_affinity(self.zsock as *mut c_void) };
+
+        if affinity == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(affinity)
+        }
+    }
+
+    pub fn set_affinity(&self, affinity: i32) {
+        unsafe { czmq_sys::zsock_set_affinity(self.zsock as *mut c_void, affinity) };
+    }
 
     pub fn set_subscribe(&self, subscribe: &str) {
         let subscribe_c = CString::new(subscribe).unwrap_or(CString::new("").unwrap()).into_raw();
@@ -677,22 +960,63 @@ impl ZSock {
         Ok(())
     }
 
-    // pub fn zsock_rate(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_rate(_self: *mut ::std::os::raw::c_void,
-    //                       rate: ::std::os::raw::c_int);
-    // pub fn zsock_recovery_ivl(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_recovery_ivl(_self: *mut ::std::os::raw::c_void,
-    //                               recovery_ivl: ::std::os::raw::c_int);
-    // pub fn zsock_sndbuf(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_sndbuf(_self: *mut ::std::os::raw::c_void,
-    //                         sndbuf: ::std::os::raw::c_int);
-    // pub fn zsock_rcvbuf(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_rcvbuf(_self: *mut ::std::os::raw::c_void,
-    //                         rcvbuf: ::std::os::raw::c_int);
+    pub fn rate(&self) -> Result<i32> {
+        let rate = unsafe { czmq_sys::zsock_rate(self.zsock as *mut c_void) };
+
+        if rate == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(rate)
+        }
+    }
+
+    pub fn set_rate(&self, rate: i32) {
+        unsafe { czmq_sys::zsock_set_rate(self.zsock as *mut c_void, rate) };
+    }
+
+    pub fn recovery_ivl(&self) -> Result<Duration> {
+        let recovery_ivl = unsafe { czmq_sys::zsock_recovery_ivl(self.zsock as *mut c_void) };
+
+        if recovery_ivl == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(Duration::from_millis(recovery_ivl as u64))
+        }
+    }
+
+    pub fn set_recovery_ivl(&self, recovery_ivl: Duration) {
+        unsafe { czmq_sys::zsock_set_recovery_ivl(self.zsock as *mut c_void, duration_ms(recovery_ivl)) };
+    }
+
+    /// Kernel send buffer size in bytes. `None` uses the OS default.
+    pub fn sndbuf(&self) -> Option<i32> {
+        let sndbuf = unsafe { czmq_sys::zsock_sndbuf(self.zsock as *mut c_void) };
+
+        if sndbuf == -1 {
+            None
+        } else {
+            Some(sndbuf)
+        }
+    }
+
+    pub fn set_sndbuf(&self, sndbuf: Option<i32>) {
+        unsafe { czmq_sys::zsock_set_sndbuf(self.zsock as *mut c_void, sndbuf.unwrap_or(-1)) };
+    }
+
+    /// Kernel receive buffer size in bytes. `None` uses the OS default.
+    pub fn rcvbuf(&self) -> Option<i32> {
+        let rcvbuf = unsafe { czmq_sys::zsock_rcvbuf(self.zsock as *mut c_void) };
+
+        if rcvbuf == -1 {
+            None
+        } else {
+            Some(rcvbuf)
+        }
+    }
+
+    pub fn set_rcvbuf(&self, rcvbuf: Option<i32>) {
+        unsafe { czmq_sys::zsock_set_rcvbuf(self.zsock as *mut c_void, rcvbuf.unwrap_or(-1)) };
+    }
 
     pub fn linger(&self) -> Result<i32> {
         let linger = unsafe { czmq_sys::zsock_linger(self.zsock as *mut c_void) };
@@ -708,27 +1032,79 @@ impl ZSock {
         unsafe { czmq_sys::zsock_set_linger(self.zsock as *mut c_void, linger) };
     }
 
-    // pub fn zsock_reconnect_ivl(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_reconnect_ivl(_self: *mut ::std::os::raw::c_void,
-    //                                reconnect_ivl: ::std::os::raw::c_int);
-    // pub fn zsock_reconnect_ivl_max(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_reconnect_ivl_max(_self: *mut ::std::os::raw::c_void,
-    //                                    reconnect_ivl_max:
-    //                                        ::std::os::raw::c_int);
-    // pub fn zsock_backlog(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_backlog(_self: *mut ::std::os::raw::c_void,
-    //                          backlog: ::std::os::raw::c_int);
-    // pub fn zsock_maxmsgsize(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_maxmsgsize(_self: *mut ::std::os::raw::c_void,
-    //                             maxmsgsize: ::std::os::raw::c_int);
-    // pub fn zsock_multicast_hops(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_multicast_hops(_self: *mut ::std::os::raw::c_void,
-    //                                 multicast_hops: ::std::os::raw::c_int);
+    /// `None` disables reconnection.
+    pub fn reconnect_ivl(&self) -> Option<Duration> {
+        let reconnect_ivl = unsafe { czmq_sys::zsock_reconnect_ivl(self.zsock as *mut c_void) };
+
+        if reconnect_ivl == -1 {
+            None
+        } else {
+            Some(Duration::from_millis(reconnect_ivl as u64))
+        }
+    }
+
+    pub fn set_reconnect_ivl(&self, reconnect_ivl: Option<Duration>) {
+        unsafe { czmq_sys::zsock_set_reconnect_ivl(self.zsock as *mut c_void, reconnect_ivl.map_or(-1, duration_ms)) };
+    }
+
+    /// The upper bound for exponential backoff between reconnects. A
+    /// zero duration disables backoff.
+    pub fn reconnect_ivl_max(&self) -> Result<Duration> {
+        let reconnect_ivl_max = unsafe { czmq_sys::zsock_reconnect_ivl_max(self.zsock as *mut c_void) };
+
+        if reconnect_ivl_max == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(Duration::from_millis(reconnect_ivl_max as u64))
+        }
+    }
+
+    pub fn set_reconnect_ivl_max(&self, reconnect_ivl_max: Duration) {
+        unsafe { czmq_sys::zsock_set_reconnect_ivl_max(self.zsock as *mut c_void, duration_ms(reconnect_ivl_max)) };
+    }
+
+    pub fn backlog(&self) -> Result<i32> {
+        let backlog = unsafe { czmq_sys::zsock_backlog(self.zsock as *mut c_void) };
+
+        if backlog == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(backlog)
+        }
+    }
+
+    pub fn set_backlog(&self, backlog: i32) {
+        unsafe { czmq_sys::zsock_set_backlog(self.zsock as *mut c_void, backlog) };
+    }
+
+    /// Largest inbound message in bytes. `None` means no limit.
+    pub fn maxmsgsize(&self) -> Option<i32> {
+        let maxmsgsize = unsafe { czmq_sys::zsock_maxmsgsize(self.zsock as *mut c_void) };
+
+        if maxmsgsize == -1 {
+            None
+        } else {
+            Some(maxmsgsize)
+        }
+    }
+
+    pub fn set_maxmsgsize(&self, maxmsgsize: Option<i32>) {
+        unsafe { czmq_sys::zsock_set_maxmsgsize(self.zsock as *mut c_void, maxmsgsize.unwrap_or(-1)) };
+    }
+
+    pub fn multicast_hops(&self) -> Result<i32> {
+        let multicast_hops = unsafe { czmq_sys::zsock_multicast_hops(self.zsock as *mut c_void) };
+
+        if multicast_hops == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(multicast_hops)
+        }
+    }
+
+    pub fn set_multicast_hops(&self, multicast_hops: i32) {
+        unsafe { czmq_sys::zsock_set_multicast_hops(self.zsock as *mut c_void, multicast_hops) };
+    }
 
     pub fn rcvtimeo(&self) -> Option<i32> {
         let timeout = unsafe { czmq_sys::zsock_rcvtimeo(self.zsock as *mut c_void) };
@@ -762,40 +1138,99 @@ impl ZSock {
         unsafe { czmq_sys::zsock_set_xpub_verbose(self.zsock as *mut c_void, if verbose { 1 } else { 0 }) };
     }
 
-    // pub fn zsock_tcp_keepalive(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_tcp_keepalive(_self: *mut ::std::os::raw::c_void,
-    //                                tcp_keepalive: ::std::os::raw::c_int);
-    // pub fn zsock_tcp_keepalive_idle(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_tcp_keepalive_idle(_self: *mut ::std::os::raw::c_void,
-    //                                     tcp_keepalive_idle:
-    //                                         ::std::os::raw::c_int);
-    // pub fn zsock_tcp_keepalive_cnt(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_tcp_keepalive_cnt(_self: *mut ::std::os::raw::c_void,
-    //                                    tcp_keepalive_cnt:
-    //                                        ::std::os::raw::c_int);
-    // pub fn zsock_tcp_keepalive_intvl(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_set_tcp_keepalive_intvl(_self: *mut ::std::os::raw::c_void,
-    //                                      tcp_keepalive_intvl:
-    //                                          ::std::os::raw::c_int);
-    // pub fn zsock_tcp_accept_filter(_self: *mut ::std::os::raw::c_void)
-    //  -> *mut ::std::os::raw::c_char;
-    // pub fn zsock_set_tcp_accept_filter(_self: *mut ::std::os::raw::c_void,
-    //                                    tcp_accept_filter:
-    //                                        *const ::std::os::raw::c_char);
+    // The TCP keepalive options all use -1 for the OS default, which
+    // maps to None.
+
+    pub fn tcp_keepalive(&self) -> Option<bool> {
+        match unsafe { czmq_sys::zsock_tcp_keepalive(self.zsock as *mut c_void) } {
+            -1 => None,
+            keepalive => Some(keepalive == 1),
+        }
+    }
+
+    pub fn set_tcp_keepalive(&self, keepalive: Option<bool>) {
+        let keepalive = match keepalive {
+            Some(true) => 1,
+            Some(false) => 0,
+            None => -1,
+        };
+        unsafe { czmq_sys::zsock_set_tcp_keepalive(self.zsock as *mut c_void, keepalive) };
+    }
+
+    pub fn tcp_keepalive_idle(&self) -> Option<Duration> {
+        match unsafe { czmq_sys::zsock_tcp_keepalive_idle(self.zsock as *mut c_void) } {
+            -1 => None,
+            idle => Some(Duration::from_secs(idle as u64)),
+        }
+    }
+
+    /// Idle time before the first keepalive probe, in whole seconds.
+    pub fn set_tcp_keepalive_idle(&self, idle: Option<Duration>) {
+        unsafe { czmq_sys::zsock_set_tcp_keepalive_idle(self.zsock as *mut c_void, idle.map_or(-1, duration_secs)) };
+    }
+
+    pub fn tcp_keepalive_cnt(&self) -> Option<i32> {
+        match unsafe { czmq_sys::zsock_tcp_keepalive_cnt(self.zsock as *mut c_void) } {
+            -1 => None,
+            cnt => Some(cnt),
+        }
+    }
+
+    pub fn set_tcp_keepalive_cnt(&self, cnt: Option<i32>) {
+        unsafe { czmq_sys::zsock_set_tcp_keepalive_cnt(self.zsock as *mut c_void, cnt.unwrap_or(-1)) };
+    }
+
+    pub fn tcp_keepalive_intvl(&self) -> Option<Duration> {
+        match unsafe { czmq_sys::zsock_tcp_keepalive_intvl(self.zsock as *mut c_void) } {
+            -1 => None,
+            intvl => Some(Duration::from_secs(intvl as u64)),
+        }
+    }
+
+    /// Time between keepalive probes, in whole seconds.
+    pub fn set_tcp_keepalive_intvl(&self, intvl: Option<Duration>) {
+        unsafe { czmq_sys::zsock_set_tcp_keepalive_intvl(self.zsock as *mut c_void, intvl.map_or(-1, duration_secs)) };
+    }
+
+    // libzmq can't read ZMQ_TCP_ACCEPT_FILTER back, so there's no
+    // getter to go with this.
+    /// Only accept TCP connections from this address or subnet, e.g.
+    /// "127.0.0.1" or "10.0.0.0/8". Each call adds a filter; an empty
+    /// string clears them all. The filters are write-only.
+    pub fn set_tcp_accept_filter(&self, filter: &str) -> Result<()> {
+        let filter_c = try!(CString::new(filter));
+        unsafe { czmq_sys::zsock_set_tcp_accept_filter(self.zsock as *mut c_void, filter_c.as_ptr()) };
+        Ok(())
+    }
 
     pub fn rcvmore(&self) -> bool {
         unsafe { czmq_sys::zsock_rcvmore(self.zsock as *mut c_void) == 1 }
     }
 
-    // pub fn zsock_fd(_self: *mut ::std::os::raw::c_void) -> SOCKET;
-    // pub fn zsock_events(_self: *mut ::std::os::raw::c_void)
-    //  -> ::std::os::raw::c_int;
-    // pub fn zsock_last_endpoint(_self: *mut ::std::os::raw::c_void)
-    //  -> *mut ::std::os::raw::c_char;
+    /// The file descriptor that becomes readable when the socket's
+    /// events may have changed. Check `events()` once it does, as it
+    /// is edge-triggered.
+    #[cfg(unix)]
+    pub fn fd(&self) -> RawFd {
+        unsafe { czmq_sys::zsock_fd(self.zsock as *mut c_void) }
+    }
+
+    pub fn events(&self) -> Result<ZSockEvents> {
+        let events = unsafe { czmq_sys::zsock_events(self.zsock as *mut c_void) };
+
+        if events == -1 {
+            Err(Error::new(ErrorKind::NonZero, ZSockError::CmdFailed))
+        } else {
+            Ok(ZSockEvents::from_bits_truncate(events))
+        }
+    }
+
+    /// The endpoint most recently bound, with any wildcard port
+    /// resolved.
+    pub fn last_endpoint(&self) -> Result<String> {
+        let ptr = unsafe { czmq_sys::zsock_last_endpoint(self.zsock as *mut c_void) };
+        owned_string(ptr)
+    }
 
     pub fn monitor(&mut self) -> Result<ZMonitor> {
         ZMonitor::new(self)
@@ -818,6 +1253,27 @@ impl ZSock {
     }
 }
 
+// As duration_ms(), for the options libzmq keeps in seconds.
+fn duration_secs(duration: Duration) -> i32 {
+    if duration.as_secs() > i32::MAX as u64 {
+        i32::MAX
+    } else {
+        duration.as_secs() as i32
+    }
+}
+
+// The string option getters return a copy that we must free.
+fn owned_string(ptr: *mut c_char) -> Result<String> {
+    if ptr == ptr::null_mut() {
+        Err(Error::new(ErrorKind::NullPtr, ZSockError::CmdFailed))
+    } else {
+        let string = unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+        let mut ptr = ptr;
+        unsafe { czmq_sys::zstr_free(&mut ptr) };
+        Ok(string)
+    }
+}
+
 impl RawInterface<c_void> for ZSock {
     unsafe fn from_raw(ptr: *mut c_void, owned: bool) -> ZSock {
         ZSock {
@@ -838,10 +1294,366 @@ impl RawInterface<c_void> for ZSock {
 
 impl Sockish for ZSock {}
 
+/// Collects socket options and endpoints, then creates the socket in
+/// one go with `build()`. Options are always applied before any
+/// endpoints are bound or connected, regardless of the order in which
+/// they were added.
+///
+/// ```no_run
+/// # use czmq::{SocketType, ZSock};
+/// let sub = ZSock::builder(SocketType::SUB)
+///     .rcvhwm(1000)
+///     .subscribe("weather")
+///     .connect("tcp://127.0.0.1:5556")
+///     .build()
+///     .unwrap();
+/// ```
+pub struct ZSockBuilder {
+    sock_type: SocketType,
+    options: Vec<ZSockOption>,
+    endpoints: Vec<ZSockEndpoint>,
+}
+
+impl ZSockBuilder {
+    pub fn new(sock_type: SocketType) -> ZSockBuilder {
+        ZSockBuilder {
+            sock_type: sock_type,
+            options: Vec::new(),
+            endpoints: Vec::new(),
+        }
+    }
+
+    pub fn sndhwm(self, sndhwm: i32) -> ZSockBuilder {
+        self.option(ZSockOption::Sndhwm(sndhwm))
+    }
+
+    pub fn rcvhwm(self, rcvhwm: i32) -> ZSockBuilder {
+        self.option(ZSockOption::Rcvhwm(rcvhwm))
+    }
+
+    pub fn linger(self, linger: i32) -> ZSockBuilder {
+        self.option(ZSockOption::Linger(linger))
+    }
+
+    pub fn identity(self, identity: &str) -> ZSockBuilder {
+        self.option(ZSockOption::Identity(identity.to_owned()))
+    }
+
+    pub fn sndtimeo(self, timeout: Option<i32>) -> ZSockBuilder {
+        self.option(ZSockOption::Sndtimeo(timeout))
+    }
+
+    pub fn rcvtimeo(self, timeout: Option<i32>) -> ZSockBuilder {
+        self.option(ZSockOption::Rcvtimeo(timeout))
+    }
+
+    /// May be called more than once to add several subscriptions.
+    pub fn subscribe(self, subscribe: &str) -> ZSockBuilder {
+        self.option(ZSockOption::Subscribe(subscribe.to_owned()))
+    }
+
+    pub fn curve_server(self, curve: bool) -> ZSockBuilder {
+        self.option(ZSockOption::CurveServer(curve))
+    }
+
+    pub fn curve_publickey(self, key: &str) -> ZSockBuilder {
+        self.option(ZSockOption::CurvePublickey(key.to_owned()))
+    }
+
+    pub fn curve_secretkey(self, key: &str) -> ZSockBuilder {
+        self.option(ZSockOption::CurveSecretkey(key.to_owned()))
+    }
+
+    pub fn curve_serverkey(self, key: &str) -> ZSockBuilder {
+        self.option(ZSockOption::CurveServerkey(key.to_owned()))
+    }
+
+    pub fn tcp_keepalive(self, keepalive: Option<bool>) -> ZSockBuilder {
+        self.option(ZSockOption::TcpKeepalive(keepalive))
+    }
+
+    pub fn tcp_keepalive_idle(self, idle: Option<Duration>) -> ZSockBuilder {
+        self.option(ZSockOption::TcpKeepaliveIdle(idle))
+    }
+
+    pub fn tcp_keepalive_cnt(self, cnt: Option<i32>) -> ZSockBuilder {
+        self.option(ZSockOption::TcpKeepaliveCnt(cnt))
+    }
+
+    pub fn tcp_keepalive_intvl(self, intvl: Option<Duration>) -> ZSockBuilder {
+        self.option(ZSockOption::TcpKeepaliveIntvl(intvl))
+    }
+
+    pub fn bind(mut self, endpoint: &str) -> ZSockBuilder {
+        self.endpoints.push(ZSockEndpoint::Bind(endpoint.to_owned()));
+        self
+    }
+
+    pub fn connect(mut self, endpoint: &str) -> ZSockBuilder {
+        self.endpoints.push(ZSockEndpoint::Connect(endpoint.to_owned()));
+        self
+    }
+
+    /// Add a comma-separated list of endpoints using the same syntax
+    /// as `ZSock::attach()`: '@' binds, '>' connects, and unprefixed
+    /// endpoints bind if `serverish` is true, otherwise connect.
+    pub fn attach(mut self, endpoints: &str, serverish: bool) -> ZSockBuilder {
+        for endpoint in endpoints.split(',').filter(|e| !e.is_empty()) {
+            self.endpoints.push(ZSockEndpoint::parse(endpoint, serverish));
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<ZSock> {
+        let zsock = unsafe { czmq_sys::zsock_new(self.sock_type as i32) };
+
+        if zsock == ptr::null_mut() {
+            return Err(Error::new(ErrorKind::NullPtr, ZSockError::CreateSock));
+        }
+
+        let zsock = ZSock {
+            zsock: zsock,
+            owned: true,
+        };
+
+        for option in &self.options {
+            try!(option.apply(&zsock));
+        }
+
+        for endpoint in &self.endpoints {
+            try!(endpoint.apply(&zsock));
+        }
+
+        Ok(zsock)
+    }
+
+    fn option(mut self, option: ZSockOption) -> ZSockBuilder {
+        self.options.push(option);
+        self
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ZSockOption {
+    Sndhwm(i32),
+    Rcvhwm(i32),
+    Linger(i32),
+    Identity(String),
+    Sndtimeo(Option<i32>),
+    Rcvtimeo(Option<i32>),
+    Subscribe(String),
+    CurveServer(bool),
+    CurvePublickey(String),
+    CurveSecretkey(String),
+    CurveServerkey(String),
+    TcpKeepalive(Option<bool>),
+    TcpKeepaliveIdle(Option<Duration>),
+    TcpKeepaliveCnt(Option<i32>),
+    TcpKeepaliveIntvl(Option<Duration>),
+}
+
+impl ZSockOption {
+    // CZMQ's setters return nothing and assert if libzmq rejects a
+    // value, so refuse anything libzmq would first, then read each
+    // option back to catch whatever CZMQ skipped quietly (e.g. an option
+    // the linked libzmq doesn't support).
+    fn apply(&self, zsock: &ZSock) -> Result<()> {
+        let step = self.step();
+
+        if let Some(reason) = self.invalid(zsock) {
+            return Err(build_error(step, None, reason.to_owned()));
+        }
+
+        match *self {
+            ZSockOption::Sndhwm(sndhwm) => {
+                zsock.set_sndhwm(sndhwm);
+                verify(step, Some(sndhwm), zsock.sndhwm().ok())
+            },
+            ZSockOption::Rcvhwm(rcvhwm) => {
+                zsock.set_rcvhwm(rcvhwm);
+                verify(step, Some(rcvhwm), zsock.rcvhwm().ok())
+            },
+            ZSockOption::Linger(linger) => {
+                zsock.set_linger(linger);
+                verify(step, linger, zsock.linger().unwrap_or(-1))
+            },
+            ZSockOption::Identity(ref identity) => {
+                if let Err(e) = zsock.set_identity(identity) {
+                    return Err(build_error(step, None, e.to_string()));
+                }
+                let found = zsock.identity().ok().and_then(|i| i.ok()).map(|i| i.to_owned());
+                verify(step, Some(identity.clone()), found)
+            },
+            ZSockOption::Sndtimeo(timeout) => {
+                zsock.set_sndtimeo(timeout);
+                verify(step, timeout.filter(|&t| t != -1), zsock.sndtimeo())
+            },
+            ZSockOption::Rcvtimeo(timeout) => {
+                zsock.set_rcvtimeo(timeout);
+                verify(step, timeout.filter(|&t| t != -1), zsock.rcvtimeo())
+            },
+            // ZMQ_SUBSCRIBE is write-only, so there's nothing to read back.
+            ZSockOption::Subscribe(ref subscribe) => {
+                zsock.set_subscribe(subscribe);
+                Ok(())
+            },
+            ZSockOption::CurveServer(curve) => {
+                zsock.set_curve_server(curve);
+                verify(step, curve, zsock.curve_server())
+            },
+            ZSockOption::CurvePublickey(ref key) => {
+                zsock.set_curve_publickey(key);
+                verify(step, Some(key.clone()), zsock.curve_publickey().ok().and_then(|k| k.ok()))
+            },
+            ZSockOption::CurveSecretkey(ref key) => {
+                zsock.set_curve_secretkey(key);
+                verify(step, Some(key.clone()), zsock.curve_secretkey().ok().and_then(|k| k.ok()))
+            },
+            ZSockOption::CurveServerkey(ref key) => {
+                zsock.set_curve_serverkey(key);
+                verify(step, Some(key.clone()), zsock.curve_serverkey().ok().and_then(|k| k.ok()))
+            },
+            ZSockOption::TcpKeepalive(keepalive) => {
+                zsock.set_tcp_keepalive(keepalive);
+                verify(step, keepalive, zsock.tcp_keepalive())
+            },
+            // The keepalive timers are whole seconds, capped at i32::MAX.
+            ZSockOption::TcpKeepaliveIdle(idle) => {
+                zsock.set_tcp_keepalive_idle(idle);
+                verify(step, idle.map(as_set), zsock.tcp_keepalive_idle())
+            },
+            ZSockOption::TcpKeepaliveCnt(cnt) => {
+                zsock.set_tcp_keepalive_cnt(cnt);
+                verify(step, cnt.filter(|&c| c != -1), zsock.tcp_keepalive_cnt())
+            },
+            ZSockOption::TcpKeepaliveIntvl(intvl) => {
+                zsock.set_tcp_keepalive_intvl(intvl);
+                verify(step, intvl.map(as_set), zsock.tcp_keepalive_intvl())
+            },
+        }
+    }
+
+    fn step(&self) -> &'static str {
+        match *self {
+            ZSockOption::Sndhwm(_) => "set sndhwm",
+            ZSockOption::Rcvhwm(_) => "set rcvhwm",
+            ZSockOption::Linger(_) => "set linger",
+            ZSockOption::Identity(_) => "set identity",
+            ZSockOption::Sndtimeo(_) => "set sndtimeo",
+            ZSockOption::Rcvtimeo(_) => "set rcvtimeo",
+            ZSockOption::Subscribe(_) => "set subscribe",
+            ZSockOption::CurveServer(_) => "set curve_server",
+            ZSockOption::CurvePublickey(_) => "set curve_publickey",
+            ZSockOption::CurveSecretkey(_) => "set curve_secretkey",
+            ZSockOption::CurveServerkey(_) => "set curve_serverkey",
+            ZSockOption::TcpKeepalive(_) => "set tcp_keepalive",
+            ZSockOption::TcpKeepaliveIdle(_) => "set tcp_keepalive_idle",
+            ZSockOption::TcpKeepaliveCnt(_) => "set tcp_keepalive_cnt",
+            ZSockOption::TcpKeepaliveIntvl(_) => "set tcp_keepalive_intvl",
+        }
+    }
+
+    fn invalid(&self, zsock: &ZSock) -> Option<&'static str> {
+        match *self {
+            ZSockOption::Sndhwm(hwm) |
+            ZSockOption::Rcvhwm(hwm) if hwm < 0 => Some("must not be negative"),
+            ZSockOption::Linger(n) |
+            ZSockOption::Sndtimeo(Some(n)) |
+            ZSockOption::Rcvtimeo(Some(n)) |
+            ZSockOption::TcpKeepaliveCnt(Some(n)) if n < -1 => Some("must be -1 or more"),
+            ZSockOption::Subscribe(_) if zsock.zsock_type() != SocketType::SUB => Some("only SUB sockets can subscribe"),
+            ZSockOption::CurveServer(_) |
+            ZSockOption::CurvePublickey(_) |
+            ZSockOption::CurveSecretkey(_) |
+            ZSockOption::CurveServerkey(_) if zmq::has("curve") != Some(true) => Some("libzmq was built without CURVE"),
+            ZSockOption::CurvePublickey(ref key) |
+            ZSockOption::CurveSecretkey(ref key) |
+            ZSockOption::CurveServerkey(ref key) if zmq::z85_decode(key).map(|k| k.len()).ok() != Some(32) =>
+                Some("not a Z85-encoded CURVE key"),
+            _ => None,
+        }
+    }
+}
+
+fn verify<T: PartialEq + fmt::Debug>(step: &'static str, wanted: T, found: T) -> Result<()> {
+    if wanted == found {
+        Ok(())
+    } else {
+        Err(build_error(step, None, format!("expected {:?}, socket has {:?}", wanted, found)))
+    }
+}
+
+fn as_set(d: Duration) -> Duration {
+    Duration::from_secs(duration_secs(d) as u64)
+}
+
+#[derive(Debug, PartialEq)]
+enum ZSockEndpoint {
+    Bind(String),
+    Connect(String),
+}
+
+impl ZSockEndpoint {
+    fn parse(endpoint: &str, serverish: bool) -> ZSockEndpoint {
+        let endpoint = endpoint.trim();
+
+        if endpoint.starts_with('@') {
+            ZSockEndpoint::Bind(endpoint[1..].to_owned())
+        } else if endpoint.starts_with('>') {
+            ZSockEndpoint::Connect(endpoint[1..].to_owned())
+        } else if serverish {
+            ZSockEndpoint::Bind(endpoint.to_owned())
+        } else {
+            ZSockEndpoint::Connect(endpoint.to_owned())
+        }
+    }
+
+    fn apply(&self, zsock: &ZSock) -> Result<()> {
+        let (step, endpoint) = match *self {
+            ZSockEndpoint::Bind(ref e) => ("bind", e),
+            ZSockEndpoint::Connect(ref e) => ("connect", e),
+        };
+
+        let endpoint_c = match CString::new(endpoint.as_str()) {
+            Ok(e) => e,
+            Err(e) => return Err(build_error(step, Some(endpoint), e.to_string())),
+        };
+
+        let rc = unsafe {
+            match *self {
+                ZSockEndpoint::Bind(_) => czmq_sys::zsock_bind(zsock.zsock, "%s\0".as_ptr() as *const i8, endpoint_c.as_ptr()),
+                ZSockEndpoint::Connect(_) => czmq_sys::zsock_connect(zsock.zsock, "%s\0".as_ptr() as *const i8, endpoint_c.as_ptr()),
+            }
+        };
+
+        if rc == -1 {
+            // Grab errno straight away, before anything else can
+            // clobber it.
+            let reason = zmq::Error::from_raw(unsafe { czmq_sys::zmq_errno() });
+            Err(build_error(step, Some(endpoint), reason.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn build_error(step: &'static str, endpoint: Option<&str>, reason: String) -> Error {
+    Error::new(ErrorKind::NonZero, ZSockError::Build {
+        step: step,
+        endpoint: endpoint.map(|e| e.to_owned()),
+        reason: reason,
+    })
+}
+
 #[derive(Debug)]
 pub enum ZSockError {
     CreateSock,
     CmdFailed,
+    Build {
+        step: &'static str,
+        endpoint: Option<String>,
+        reason: String,
+    },
 }
 
 impl fmt::Display for ZSockError {
@@ -849,6 +1661,8 @@ impl fmt::Display for ZSockError {
         match *self {
             ZSockError::CreateSock => write!(f, "Could not create socket"),
             ZSockError::CmdFailed => write!(f, "Socket command failed"),
+            ZSockError::Build { step, endpoint: Some(ref endpoint), ref reason } => write!(f, "Could not {} {}: {}", step, endpoint, reason),
+            ZSockError::Build { step, endpoint: None, ref reason } => write!(f, "Could not {}: {}", step, reason),
         }
     }
 }
@@ -858,12 +1672,15 @@ impl error::Error for ZSockError {
         match *self {
             ZSockError::CreateSock => "Could not create socket",
             ZSockError::CmdFailed => "Socket command failed",
+            ZSockError::Build { .. } => "Could not build socket",
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+    use std::net::TcpStream;
     use std::thread::sleep;
     use std::time::Duration;
     use super::*;
@@ -988,6 +1805,213 @@ mod tests {
         assert!(zsock.is_ok());
     }
 
+    #[test]
+    fn test_builder() {
+        ZSys::init();
+
+        let pull = ZSock::builder(SocketType::PULL)
+            .rcvhwm(500)
+            .rcvtimeo(Some(500))
+            .linger(0)
+            .identity("moo")
+            .tcp_keepalive(Some(true))
+            .bind("inproc://zsock_test_builder")
+            .attach("@inproc://zsock_test_builder_2", false)
+            .build()
+            .unwrap();
+
+        assert_eq!(pull.rcvhwm().unwrap(), 500);
+        assert_eq!(pull.rcvtimeo(), Some(500));
+        assert_eq!(pull.linger().unwrap(), 0);
+        assert_eq!(pull.identity().unwrap().unwrap(), "moo");
+        assert_eq!(pull.tcp_keepalive(), Some(true));
+
+        let push = ZSock::builder(SocketType::PUSH)
+            .attach(">inproc://zsock_test_builder,inproc://zsock_test_builder_2", false)
+            .build()
+            .unwrap();
+
+        push.send_str("cow").unwrap();
+        push.send_str("cow").unwrap();
+        assert_eq!(pull.recv_str().unwrap().unwrap(), "cow");
+        assert_eq!(pull.recv_str().unwrap().unwrap(), "cow");
+    }
+
+    #[test]
+    fn test_builder_subscribe() {
+        ZSys::init();
+
+        let publisher = ZSock::new_pub("inproc://zsock_test_builder_subscribe").unwrap();
+        let sub = ZSock::builder(SocketType::SUB)
+            .rcvtimeo(Some(200))
+            .subscribe("a")
+            .subscribe("b")
+            .connect("inproc://zsock_test_builder_subscribe")
+            .build()
+            .unwrap();
+        sleep(Duration::from_millis(50));
+
+        publisher.send_str("a").unwrap();
+        publisher.send_str("c").unwrap();
+        publisher.send_str("b").unwrap();
+        assert_eq!(sub.recv_str().unwrap().unwrap(), "a");
+        assert_eq!(sub.recv_str().unwrap().unwrap(), "b");
+        assert!(sub.recv_str().is_err());
+    }
+
+    #[test]
+    fn test_builder_error() {
+        ZSys::init();
+
+        let err = ZSock::builder(SocketType::PULL)
+            .bind("inproc://zsock_test_builder_error")
+            .connect("moo://nowhere")
+            .build()
+            .err()
+            .unwrap();
+
+        let msg = err.to_string();
+        assert!(msg.contains("Could not connect moo://nowhere"));
+    }
+
+    #[test]
+    fn test_builder_option_error() {
+        ZSys::init();
+
+        let err = ZSock::builder(SocketType::PULL)
+            .subscribe("a")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Could not set subscribe"));
+
+        let err = ZSock::builder(SocketType::PULL)
+            .sndhwm(-5)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Could not set sndhwm"));
+
+        let err = ZSock::builder(SocketType::PULL)
+            .curve_publickey("moo")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Could not set curve_publickey"));
+    }
+
+    #[test]
+    fn test_builder_attach_syntax() {
+        assert_eq!(ZSockEndpoint::parse("@inproc://a", false), ZSockEndpoint::Bind("inproc://a".into()));
+        assert_eq!(ZSockEndpoint::parse(">inproc://a", true), ZSockEndpoint::Connect("inproc://a".into()));
+        assert_eq!(ZSockEndpoint::parse("inproc://a", true), ZSockEndpoint::Bind("inproc://a".into()));
+        assert_eq!(ZSockEndpoint::parse("inproc://a", false), ZSockEndpoint::Connect("inproc://a".into()));
+    }
+
+    #[cfg(feature = "draft")]
+    #[test]
+    fn test_new_draft() {
+        ZSys::init();
+
+        let zsock = ZSock::new_draft(DraftSocketType::SERVER);
+        assert_eq!(zsock.draft_type(), Some(DraftSocketType::SERVER));
+
+        let zsock = ZSock::new(SocketType::REP);
+        assert_eq!(zsock.draft_type(), None);
+    }
+
+    #[cfg(feature = "draft")]
+    #[test]
+    fn test_server_client() {
+        ZSys::init();
+
+        let mut server = ZSock::new_server("@inproc://zsock_test_server_client").unwrap();
+        server.set_rcvtimeo(Some(500));
+        let mut client = ZSock::new_client(">inproc://zsock_test_server_client").unwrap();
+        client.set_rcvtimeo(Some(500));
+        assert_eq!(server.draft_type(), Some(DraftSocketType::SERVER));
+        assert_eq!(client.draft_type(), Some(DraftSocketType::CLIENT));
+
+        ZFrame::from("moo").unwrap().send(&mut client, None).unwrap();
+        let frame = ZFrame::recv(&mut server).unwrap();
+        let routing_id = frame.routing_id();
+        assert!(routing_id != 0);
+
+        let reply = ZFrame::from("cow").unwrap();
+        reply.set_routing_id(routing_id);
+        reply.send(&mut server, None).unwrap();
+        assert_eq!(client.recv_str().unwrap().unwrap(), "cow");
+
+        let msg = ZMsg::new();
+        msg.addstr("moo").unwrap();
+        msg.send(&mut client).unwrap();
+        let msg = ZMsg::recv(&mut server).unwrap();
+        assert_eq!(msg.routing_id(), routing_id);
+
+        let reply = ZMsg::new();
+        reply.addstr("cow").unwrap();
+        reply.set_routing_id(msg.routing_id());
+        reply.send(&mut server).unwrap();
+        assert_eq!(client.recv_str().unwrap().unwrap(), "cow");
+    }
+
+    #[cfg(feature = "draft")]
+    #[test]
+    fn test_radio_dish() {
+        ZSys::init();
+
+        let mut dish = ZSock::new_dish("@inproc://zsock_test_radio_dish").unwrap();
+        dish.set_rcvtimeo(Some(200));
+        dish.join("weather").unwrap();
+        let mut radio = ZSock::new_radio(">inproc://zsock_test_radio_dish").unwrap();
+        sleep(Duration::from_millis(50));
+
+        let frame = ZFrame::from("sport").unwrap();
+        frame.set_group("sport").unwrap();
+        frame.send(&mut radio, None).unwrap();
+
+        let frame = ZFrame::from("sunny").unwrap();
+        frame.set_group("weather").unwrap();
+        frame.send(&mut radio, None).unwrap();
+
+        let frame = ZFrame::recv(&mut dish).unwrap();
+        assert_eq!(frame.group().unwrap(), "weather");
+        assert_eq!(frame.data().unwrap().unwrap(), "sunny");
+
+        dish.leave("weather").unwrap();
+        sleep(Duration::from_millis(50));
+
+        let frame = ZFrame::from("rainy").unwrap();
+        frame.set_group("weather").unwrap();
+        frame.send(&mut radio, None).unwrap();
+        assert!(ZFrame::recv(&mut dish).is_err());
+    }
+
+    #[cfg(feature = "draft")]
+    #[test]
+    fn test_group_too_long() {
+        ZSys::init();
+
+        let frame = ZFrame::from("moo").unwrap();
+        assert!(frame.group().is_none());
+        assert!(frame.set_group("abcdefghijklmnop").is_err());
+    }
+
+    #[cfg(feature = "draft")]
+    #[test]
+    fn test_scatter_gather() {
+        ZSys::init();
+
+        let gather = ZSock::new_gather("@inproc://zsock_test_scatter_gather").unwrap();
+        gather.set_rcvtimeo(Some(500));
+        let scatter = ZSock::new_scatter(">inproc://zsock_test_scatter_gather").unwrap();
+        assert_eq!(gather.draft_type(), Some(DraftSocketType::GATHER));
+        assert_eq!(scatter.draft_type(), Some(DraftSocketType::SCATTER));
+
+        scatter.send_str("moo").unwrap();
+        assert_eq!(gather.recv_str().unwrap().unwrap(), "moo");
+    }
+
     #[test]
     fn test_bind() {
         ZSys::init();
@@ -1131,6 +2155,16 @@ mod tests {
         assert_eq!(zsock.rcvhwm().unwrap(), 2000);
     }
 
+    #[test]
+    fn test_set_unbounded() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PUSH);
+        zsock.set_unbounded();
+        assert_eq!(zsock.sndhwm().unwrap(), 0);
+        assert_eq!(zsock.rcvhwm().unwrap(), 0);
+    }
+
     #[test]
     fn test_subscribe() {
         ZSys::init();
@@ -1299,6 +2333,368 @@ mod tests {
         assert_eq!(x, 2);
     }
 
+    #[test]
+    fn test_tos() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::DEALER);
+        zsock.set_tos(1);
+        assert_eq!(zsock.tos().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_router_handover() {
+        ZSys::init();
+
+        let mut router = ZSock::new(SocketType::ROUTER);
+        router.set_rcvtimeo(Some(500));
+        router.set_router_handover(true);
+        router.bind("inproc://zsock_test_router_handover").unwrap();
+
+        let dealer1 = ZSock::new(SocketType::DEALER);
+        dealer1.set_identity("moo").unwrap();
+        dealer1.connect("inproc://zsock_test_router_handover").unwrap();
+        dealer1.send_str("first").unwrap();
+        assert_eq!(ZMsg::recv(&mut router).unwrap().popstr().unwrap().unwrap(), "moo");
+
+        // The second peer takes over the identity rather than being
+        // ignored.
+        let dealer2 = ZSock::new(SocketType::DEALER);
+        dealer2.set_rcvtimeo(Some(500));
+        dealer2.set_identity("moo").unwrap();
+        dealer2.connect("inproc://zsock_test_router_handover").unwrap();
+        dealer2.send_str("second").unwrap();
+        ZMsg::recv(&mut router).unwrap();
+
+        let msg = ZMsg::new();
+        msg.addstr("moo").unwrap();
+        msg.addstr("cow").unwrap();
+        msg.send(&mut router).unwrap();
+        assert_eq!(dealer2.recv_str().unwrap().unwrap(), "cow");
+    }
+
+    #[test]
+    fn test_router_mandatory() {
+        ZSys::init();
+
+        let mut router = ZSock::new(SocketType::ROUTER);
+        router.set_router_mandatory(true);
+        router.bind("inproc://zsock_test_router_mandatory").unwrap();
+
+        let msg = ZMsg::new();
+        msg.addstr("nobody").unwrap();
+        msg.addstr("moo").unwrap();
+        assert!(msg.send(&mut router).is_err());
+    }
+
+    #[test]
+    fn test_probe_router() {
+        ZSys::init();
+
+        let mut router = ZSock::new(SocketType::ROUTER);
+        router.set_rcvtimeo(Some(500));
+        router.bind("inproc://zsock_test_probe_router").unwrap();
+
+        let dealer = ZSock::new(SocketType::DEALER);
+        dealer.set_probe_router(true);
+        dealer.connect("inproc://zsock_test_probe_router").unwrap();
+
+        // The probe is an identity frame followed by an empty frame
+        let msg = ZMsg::recv(&mut router).unwrap();
+        assert_eq!(msg.size(), 2);
+    }
+
+    #[test]
+    fn test_req_relaxed() {
+        ZSys::init();
+
+        let _rep = ZSock::new_rep("inproc://zsock_test_req_relaxed").unwrap();
+        let req = ZSock::new_req("inproc://zsock_test_req_relaxed").unwrap();
+        req.send_str("moo").unwrap();
+        assert!(req.send_str("moo").is_err());
+
+        let req = ZSock::new(SocketType::REQ);
+        req.set_req_relaxed(true);
+        req.connect("inproc://zsock_test_req_relaxed").unwrap();
+        req.send_str("moo").unwrap();
+        assert!(req.send_str("moo").is_ok());
+    }
+
+    #[test]
+    fn test_req_correlate() {
+        ZSys::init();
+
+        let mut router = ZSock::new(SocketType::ROUTER);
+        router.set_rcvtimeo(Some(500));
+        router.bind("inproc://zsock_test_req_correlate").unwrap();
+
+        let req = ZSock::new(SocketType::REQ);
+        req.set_req_correlate(true);
+        req.connect("inproc://zsock_test_req_correlate").unwrap();
+        req.send_str("moo").unwrap();
+
+        // Identity, request id, delimiter and body
+        let msg = ZMsg::recv(&mut router).unwrap();
+        assert_eq!(msg.size(), 4);
+    }
+
+    #[test]
+    fn test_conflate() {
+        ZSys::init();
+
+        let pull = ZSock::new(SocketType::PULL);
+        pull.set_rcvtimeo(Some(100));
+        pull.set_conflate(true);
+        pull.bind("inproc://zsock_test_conflate").unwrap();
+
+        let push = ZSock::new_push("inproc://zsock_test_conflate").unwrap();
+        push.send_str("first").unwrap();
+        push.send_str("last").unwrap();
+        sleep(Duration::from_millis(100));
+
+        assert_eq!(pull.recv_str().unwrap().unwrap(), "last");
+        assert!(pull.recv_str().is_err());
+    }
+
+    #[test]
+    fn test_ipv6() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::REP);
+        assert!(!zsock.ipv6());
+        zsock.set_ipv6(true);
+        assert!(zsock.ipv6());
+    }
+
+    #[test]
+    fn test_immediate() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::REQ);
+        assert!(!zsock.immediate());
+        zsock.set_immediate(true);
+        assert!(zsock.immediate());
+    }
+
+    #[test]
+    fn test_router_raw() {
+        ZSys::init();
+
+        let mut router = ZSock::new(SocketType::ROUTER);
+        router.set_rcvtimeo(Some(500));
+        router.set_router_raw(true);
+        router.bind("tcp://127.0.0.1:*").unwrap();
+
+        let endpoint = router.last_endpoint().unwrap();
+        let mut stream = TcpStream::connect(&endpoint["tcp://".len()..]).unwrap();
+        stream.write_all(b"moo").unwrap();
+
+        // Raw sockets announce new connections with an empty frame
+        let data = loop {
+            let msg = ZMsg::recv(&mut router).unwrap();
+            msg.popbytes().unwrap();
+            let data = msg.popbytes().unwrap().unwrap();
+            if !data.is_empty() {
+                break data;
+            }
+        };
+        assert_eq!(data, b"moo");
+    }
+
+    #[test]
+    fn test_affinity() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::REP);
+        zsock.set_affinity(1);
+        assert_eq!(zsock.affinity().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_rate() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PUB);
+        zsock.set_rate(200);
+        assert_eq!(zsock.rate().unwrap(), 200);
+    }
+
+    #[test]
+    fn test_recovery_ivl() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PUB);
+        zsock.set_recovery_ivl(Duration::from_millis(2500));
+        assert_eq!(zsock.recovery_ivl().unwrap(), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_sndbuf() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PUB);
+        zsock.set_sndbuf(Some(65536));
+        assert_eq!(zsock.sndbuf(), Some(65536));
+        zsock.set_sndbuf(None);
+        assert_eq!(zsock.sndbuf(), None);
+    }
+
+    #[test]
+    fn test_rcvbuf() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::SUB);
+        zsock.set_rcvbuf(Some(65536));
+        assert_eq!(zsock.rcvbuf(), Some(65536));
+        zsock.set_rcvbuf(None);
+        assert_eq!(zsock.rcvbuf(), None);
+    }
+
+    #[test]
+    fn test_reconnect_ivl() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::DEALER);
+        zsock.set_reconnect_ivl(Some(Duration::from_millis(250)));
+        assert_eq!(zsock.reconnect_ivl(), Some(Duration::from_millis(250)));
+        zsock.set_reconnect_ivl(None);
+        assert_eq!(zsock.reconnect_ivl(), None);
+    }
+
+    #[test]
+    fn test_reconnect_ivl_max() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::DEALER);
+        zsock.set_reconnect_ivl_max(Duration::from_secs(5));
+        assert_eq!(zsock.reconnect_ivl_max().unwrap(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_backlog() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::ROUTER);
+        zsock.set_backlog(50);
+        assert_eq!(zsock.backlog().unwrap(), 50);
+    }
+
+    #[test]
+    fn test_maxmsgsize() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PULL);
+        assert_eq!(zsock.maxmsgsize(), None);
+        zsock.set_maxmsgsize(Some(1024));
+        assert_eq!(zsock.maxmsgsize(), Some(1024));
+    }
+
+    #[test]
+    fn test_multicast_hops() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PUB);
+        zsock.set_multicast_hops(4);
+        assert_eq!(zsock.multicast_hops().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_tcp_keepalive() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::DEALER);
+        assert_eq!(zsock.tcp_keepalive(), None);
+        zsock.set_tcp_keepalive(Some(true));
+        assert_eq!(zsock.tcp_keepalive(), Some(true));
+        zsock.set_tcp_keepalive(Some(false));
+        assert_eq!(zsock.tcp_keepalive(), Some(false));
+
+        zsock.set_tcp_keepalive_idle(Some(Duration::from_secs(30)));
+        assert_eq!(zsock.tcp_keepalive_idle(), Some(Duration::from_secs(30)));
+        zsock.set_tcp_keepalive_idle(None);
+        assert_eq!(zsock.tcp_keepalive_idle(), None);
+
+        zsock.set_tcp_keepalive_cnt(Some(3));
+        assert_eq!(zsock.tcp_keepalive_cnt(), Some(3));
+
+        zsock.set_tcp_keepalive_intvl(Some(Duration::from_secs(10)));
+        assert_eq!(zsock.tcp_keepalive_intvl(), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_tcp_accept_filter() {
+        ZSys::init();
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_tcp_accept_filter("10.0.0.1").unwrap();
+        server.set_rcvtimeo(Some(200));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_linger(0);
+        client.set_sndtimeo(Some(200));
+        client.connect(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+        let _ = client.send_str("moo");
+        assert!(server.recv_str().is_err());
+
+        let server = ZSock::new(SocketType::PULL);
+        server.set_tcp_accept_filter("127.0.0.1").unwrap();
+        server.set_rcvtimeo(Some(500));
+        let port = server.bind("tcp://127.0.0.1:*[60000-]").unwrap();
+
+        let client = ZSock::new(SocketType::PUSH);
+        client.set_linger(0);
+        client.connect(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+        client.send_str("moo").unwrap();
+        assert_eq!(server.recv_str().unwrap().unwrap(), "moo");
+    }
+
+    #[test]
+    fn test_duration_ms() {
+        assert_eq!(duration_ms(Duration::from_millis(1500)), 1500);
+        assert_eq!(duration_ms(Duration::from_secs(3_000_000)), i32::MAX);
+        assert_eq!(duration_ms(Duration::from_secs(u64::MAX)), i32::MAX);
+    }
+
+    #[test]
+    fn test_duration_secs() {
+        assert_eq!(duration_secs(Duration::from_millis(1500)), 1);
+        assert_eq!(duration_secs(Duration::from_secs(u32::MAX as u64)), i32::MAX);
+        assert_eq!(duration_secs(Duration::from_secs(u64::MAX)), i32::MAX);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_fd() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PULL);
+        assert!(zsock.fd() >= 0);
+    }
+
+    #[test]
+    fn test_events() {
+        ZSys::init();
+
+        let pull = ZSock::new_pull("inproc://zsock_test_events").unwrap();
+        let push = ZSock::new_push("inproc://zsock_test_events").unwrap();
+        assert!(!pull.events().unwrap().contains(ZSOCK_POLLIN));
+        assert!(push.events().unwrap().contains(ZSOCK_POLLOUT));
+
+        push.send_str("moo").unwrap();
+        sleep(Duration::from_millis(50));
+        assert!(pull.events().unwrap().contains(ZSOCK_POLLIN));
+    }
+
+    #[test]
+    fn test_last_endpoint() {
+        ZSys::init();
+
+        let zsock = ZSock::new(SocketType::PULL);
+        let port = zsock.bind("tcp://127.0.0.1:*").unwrap();
+        assert_eq!(zsock.last_endpoint().unwrap(), format!("tcp://127.0.0.1:{}", port));
+    }
+
     #[test]
     fn test_monitor() {
         ZSys::init();
@@ -3,6 +3,7 @@
 use {czmq_sys, RawInterface, Result};
 use error::{Error, ErrorKind};
 use std::{error, fmt, ptr};
+use std::ffi::{CStr, CString};
 use std::os::raw::c_void;
 use std::sync::{Once, ONCE_INIT};
 use zsock::ZSock;
@@ -36,6 +37,20 @@ impl ZSys {
     pub fn is_interrupted() -> bool {
         unsafe { czmq_sys::zsys_interrupted == 1 }
     }
+
+    /// Set the network interface used by UDP services such as
+    /// ZBeacon, e.g. "eth0". "*" binds to all interfaces and
+    /// broadcasts to 255.255.255.255. An empty string uses the first
+    /// broadcast-capable interface.
+    pub fn set_interface(value: &str) -> Result<()> {
+        let value_c = try!(CString::new(value));
+        unsafe { czmq_sys::zsys_set_interface(value_c.as_ptr()) };
+        Ok(())
+    }
+
+    pub fn interface() -> String {
+        unsafe { CStr::from_ptr(czmq_sys::zsys_interface()).to_string_lossy().into_owned() }
+    }
 }
 
 #[derive(Debug)]
@@ -74,6 +89,14 @@ mod tests {
         assert_eq!(frontend.recv_str().unwrap().unwrap(), "My family laughed when I told them I was going to be a comedian. Well...they aren't laughing now!");
     }
 
+    #[test]
+    fn test_interface() {
+        ZSys::init();
+
+        ZSys::set_interface("*").unwrap();
+        assert_eq!(ZSys::interface(), "*");
+    }
+
     #[test]
     fn test_is_interrupted() {
         // This test is half hearted as we can't test the interrupt